@@ -1,6 +1,4 @@
 #![cfg(feature = "bin")]
-#[cfg(feature = "verbose-log")]
-use std::sync::atomic::AtomicUsize;
 use std::{
     fs::File,
     io::{BufWriter, Write},
@@ -10,7 +8,7 @@ use std::{
 use criterion::{Criterion, Throughput, criterion_group, criterion_main};
 use rayon::iter::{IntoParallelIterator as _, IntoParallelRefIterator, ParallelIterator};
 use tempfile::TempDir;
-use urldecoder::decode_file;
+use urldecoder::{decode_file, log::NoOpLogger};
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
@@ -79,23 +77,8 @@ fn bench_decode_throughput(c: &mut Criterion) {
         b.iter(|| {
             let escape_space = false;
             let dry_run = true;
-            #[cfg(feature = "verbose-log")]
-            let processed_count = AtomicUsize::new(0);
-            #[cfg(feature = "verbose-log")]
-            let changed_count = AtomicUsize::new(0);
             paths.par_iter().for_each(|path| {
-                decode_file(
-                    path,
-                    escape_space,
-                    dry_run,
-                    #[cfg(feature = "verbose-log")]
-                    false,
-                    #[cfg(feature = "verbose-log")]
-                    &processed_count,
-                    #[cfg(feature = "verbose-log")]
-                    &changed_count,
-                )
-                .unwrap()
+                decode_file(path, escape_space, dry_run, &mut NoOpLogger).unwrap();
             })
         })
     });
@@ -107,23 +90,8 @@ fn bench_decode_throughput(c: &mut Criterion) {
         b.iter(|| {
             let escape_space = false;
             let dry_run = false;
-            #[cfg(feature = "verbose-log")]
-            let processed_count = AtomicUsize::new(0);
-            #[cfg(feature = "verbose-log")]
-            let changed_count = AtomicUsize::new(0);
             paths.par_iter().for_each(|path| {
-                decode_file(
-                    path,
-                    escape_space,
-                    dry_run,
-                    #[cfg(feature = "verbose-log")]
-                    false,
-                    #[cfg(feature = "verbose-log")]
-                    &processed_count,
-                    #[cfg(feature = "verbose-log")]
-                    &changed_count,
-                )
-                .unwrap()
+                decode_file(path, escape_space, dry_run, &mut NoOpLogger).unwrap();
             })
         })
     });
@@ -1,10 +1,8 @@
-#[cfg(feature = "verbose-log")]
-use std::sync::atomic::AtomicUsize;
 use std::{hint::black_box, io::Write};
 
 use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
 use tempfile::NamedTempFile;
-use urldecoder::decode_file;
+use urldecoder::{decode_file, log::NoOpLogger};
 
 const SMALL_FILE_SIZE: u64 = 32 * 1024; // 32 KB
 const LARGE_FILE_SIZE: u64 = 10 * 1024 * 1024; // 10 MB
@@ -53,11 +51,6 @@ fn bench_file_decode_dry_run(c: &mut Criterion) {
         temp.into_temp_path()
     });
 
-    #[cfg(feature = "verbose-log")]
-    let p_counter = AtomicUsize::new(0);
-    #[cfg(feature = "verbose-log")]
-    let c_counter = AtomicUsize::new(0);
-
     let safe_suffix = if cfg!(feature = "safe") {
         " (safe)"
     } else {
@@ -69,22 +62,12 @@ fn bench_file_decode_dry_run(c: &mut Criterion) {
     small_group.throughput(Throughput::Bytes(SMALL_FILE_SIZE));
     small_group.bench_function("decode_small_dry_run".to_string() + safe_suffix, |b| {
         b.iter(|| {
-            #[cfg(feature = "verbose-log")]
-            {
-                decode_file(
-                    black_box(&small_path),
-                    black_box(true),
-                    black_box(true),
-                    black_box(false),
-                    black_box(&p_counter),
-                    black_box(&c_counter),
-                )
-            }
-
-            #[cfg(not(feature = "verbose-log"))]
-            {
-                decode_file(black_box(&small_path), black_box(true), black_box(true))
-            }
+            decode_file(
+                black_box(&small_path),
+                black_box(true),
+                black_box(true),
+                &mut NoOpLogger,
+            )
         })
     });
     small_group.finish();
@@ -94,22 +77,12 @@ fn bench_file_decode_dry_run(c: &mut Criterion) {
     large_group.throughput(Throughput::Bytes(LARGE_FILE_SIZE));
     large_group.bench_function("decode_large_dry_run".to_string() + safe_suffix, |b| {
         b.iter(|| {
-            #[cfg(feature = "verbose-log")]
-            {
-                decode_file(
-                    black_box(&large_path),
-                    black_box(true),
-                    black_box(true),
-                    black_box(false),
-                    black_box(&p_counter),
-                    black_box(&c_counter),
-                )
-            }
-
-            #[cfg(not(feature = "verbose-log"))]
-            {
-                decode_file(black_box(&large_path), black_box(true), black_box(true))
-            }
+            decode_file(
+                black_box(&large_path),
+                black_box(true),
+                black_box(true),
+                &mut NoOpLogger,
+            )
         })
     });
     large_group.finish();
@@ -119,11 +92,6 @@ fn bench_file_decode(c: &mut Criterion) {
     let small_data = generate_data(SMALL_FILE_SIZE);
     let large_data = generate_data(LARGE_FILE_SIZE);
 
-    #[cfg(feature = "verbose-log")]
-    let p_counter = AtomicUsize::new(0);
-    #[cfg(feature = "verbose-log")]
-    let c_counter = AtomicUsize::new(0);
-
     let safe_suffix = if cfg!(feature = "safe") {
         " (safe)"
     } else {
@@ -142,22 +110,12 @@ fn bench_file_decode(c: &mut Criterion) {
                 temp.into_temp_path()
             },
             |small_path| {
-                #[cfg(feature = "verbose-log")]
-                {
-                    decode_file(
-                        black_box(&small_path),
-                        black_box(true),
-                        black_box(false),
-                        black_box(false),
-                        black_box(&p_counter),
-                        black_box(&c_counter),
-                    )
-                }
-
-                #[cfg(not(feature = "verbose-log"))]
-                {
-                    decode_file(black_box(&small_path), black_box(true), black_box(false))
-                }
+                decode_file(
+                    black_box(&small_path),
+                    black_box(true),
+                    black_box(false),
+                    &mut NoOpLogger,
+                )
             },
             BatchSize::SmallInput,
         )
@@ -176,22 +134,12 @@ fn bench_file_decode(c: &mut Criterion) {
                 temp.into_temp_path()
             },
             |large_path| {
-                #[cfg(feature = "verbose-log")]
-                {
-                    decode_file(
-                        black_box(&large_path),
-                        black_box(true),
-                        black_box(false),
-                        black_box(false),
-                        black_box(&p_counter),
-                        black_box(&c_counter),
-                    )
-                }
-
-                #[cfg(not(feature = "verbose-log"))]
-                {
-                    decode_file(black_box(&large_path), black_box(true), black_box(false))
-                }
+                decode_file(
+                    black_box(&large_path),
+                    black_box(true),
+                    black_box(false),
+                    &mut NoOpLogger,
+                )
             },
             BatchSize::SmallInput,
         );
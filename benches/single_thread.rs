@@ -1,9 +1,7 @@
 use std::{hint::black_box, io};
 
 use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
-#[cfg(feature = "verbose-log")]
-use urldecoder::log::{DecodeLogger, NoOpLogger, VerboseLogger};
-use urldecoder::{decode_in_place, decode_slice_to_writer};
+use urldecoder::{decode_in_place, decode_slice_to_writer, log::NoOpLogger};
 
 const STREAM_SIZE: u64 = 128 * 1024 * 1024;
 
@@ -51,41 +49,20 @@ fn bench_decode(c: &mut Criterion) {
     group.bench_function("slice_to_sink", |b| {
         b.iter(|| {
             let mut sink = io::sink();
-
-            #[cfg(feature = "verbose-log")]
-            {
-                let mut logger = NoOpLogger;
-                decode_slice_to_writer(
-                    black_box(&full_data),
-                    black_box(&mut sink),
-                    black_box(true),
-                    #[cfg(feature = "verbose-log")]
-                    black_box(&mut logger),
-                )
-                .unwrap()
-            }
-            #[cfg(not(feature = "verbose-log"))]
-            {
-                decode_slice_to_writer(black_box(&full_data), black_box(&mut sink), black_box(true))
-                    .unwrap()
-            }
+            decode_slice_to_writer(
+                black_box(&full_data),
+                black_box(&mut sink),
+                black_box(true),
+                &mut NoOpLogger,
+            )
+            .unwrap()
         })
     });
     // 7.8869 GiB/s
     group.bench_function("decode_in_place", |b| {
         b.iter_batched_ref(
             || full_data.clone(),
-            |full_data| {
-                #[cfg(feature = "verbose-log")]
-                {
-                    let mut logger = VerboseLogger::new();
-                    decode_in_place(black_box(full_data), black_box(true), &mut logger)
-                }
-                #[cfg(not(feature = "verbose-log"))]
-                {
-                    decode_in_place(black_box(full_data), black_box(true))
-                }
-            },
+            |full_data| decode_in_place(black_box(full_data), black_box(true), &mut NoOpLogger),
             BatchSize::SmallInput,
         )
     });
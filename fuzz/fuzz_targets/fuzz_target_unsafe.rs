@@ -4,7 +4,7 @@ use std::{borrow::Cow, sync::LazyLock};
 use libfuzzer_sys::fuzz_target;
 use memchr::memmem::Finder;
 use regex::Regex;
-use urldecoder::{decode_in_place, decode_str};
+use urldecoder::{decode_in_place, decode_str, log::NoOpLogger};
 use urlencoding::decode;
 
 static REGEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -26,19 +26,30 @@ pub fn decode_url_strict(code: &str, escape_space: bool) -> Result<(Cow<'_, str>
             return Cow::Borrowed("");
         }
 
-        let url = &caps[0];
+        let m = caps.get(0).unwrap();
+        let url = m.as_str();
         if url.rfind('%').is_none() {
             return Cow::Owned(url.to_owned());
         }
+        // the quote enclosing the URL must stay encoded
+        let delim = match code.as_bytes()[..m.start()].last() {
+            Some(b'"') => Some(("\"", "%22")),
+            Some(b'\'') => Some(("'", "%27")),
+            Some(b'`') => Some(("`", "%60")),
+            _ => None,
+        };
 
         // 核心改动：不再 unwrap_or，而是处理 Result
         match decode(url) {
             Ok(decoded_cow) => {
-                let result = if escape_space {
+                let mut result = if escape_space {
                     decoded_cow.replace(' ', "%20")
                 } else {
                     decoded_cow.into_owned()
                 };
+                if let Some((quote, encoded)) = delim {
+                    result = result.replace(quote, encoded);
+                }
 
                 if url != result {
                     replaced = true;
@@ -60,7 +71,7 @@ pub fn decode_url_strict(code: &str, escape_space: bool) -> Result<(Cow<'_, str>
 }
 
 fn test_basic(input_str: &str, ref_res: Result<&(Cow<str>, bool), &()>, escape_space: bool) {
-    let my_res = decode_str(input_str, escape_space);
+    let my_res = decode_str(input_str, escape_space, &mut NoOpLogger);
 
     match (ref_res, my_res.as_ref()) {
         // Case 1: 参考实现认为这是错误的编码，但你的实现成功解码了。
@@ -104,7 +115,7 @@ fn test_basic(input_str: &str, ref_res: Result<&(Cow<str>, bool), &()>, escape_s
 }
 
 fn test_in_place(mut input: Vec<u8>, ref_res: (Cow<str>, bool), escape_space: bool) {
-    let res = decode_in_place(&mut input, escape_space);
+    let res = decode_in_place(&mut input, escape_space, &mut NoOpLogger);
     let (my_res, my_changed) = (&input[0..res], res < input.len());
 
     assert_eq!(
@@ -0,0 +1,184 @@
+use std::{
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+use memchr::{memchr, memrchr};
+
+use crate::{
+    decode_hex_pair,
+    log::{ChangeRecorder, DecodeLogger, NoOpRecorder, Recorder, utf16_len},
+};
+
+/// Decode the request paths and `Referer` fields of an access log in Common or
+/// Combined Log Format, line by line:
+///
+/// ```text
+/// 1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] "GET /s?q=%E4%B8%AD HTTP/1.1" 200 2326 "https://x.com/%E6%96%87" "curl/8.0"
+/// ```
+///
+/// Spaces, quotes and control characters stay encoded so that every line keeps
+/// its fields. Other text is copied as is. Returns whether anything changed.
+pub fn decode_access_log<R: BufRead, W: Write>(
+    reader: R,
+    writer: &mut W,
+    logger: &mut impl DecodeLogger,
+) -> io::Result<bool> {
+    decode_access_log_counted(reader, writer, logger).map(|urls| urls > 0)
+}
+
+/// Like [`decode_access_log`], but returns how many fields changed instead of
+/// whether any did.
+pub(crate) fn decode_access_log_counted<R: BufRead, W: Write>(
+    reader: R,
+    writer: &mut W,
+    logger: &mut impl DecodeLogger,
+) -> io::Result<usize> {
+    if logger.enabled() {
+        return decode_lines(reader, writer, &mut ChangeRecorder::new(logger));
+    }
+    decode_lines(reader, writer, &mut NoOpRecorder)
+}
+
+fn decode_lines<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    logger: &mut impl Recorder,
+) -> io::Result<usize> {
+    let mut changed = 0;
+    let mut line = Vec::new();
+    let mut at = LineStart { offset: 0, line: 1 };
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line)?;
+        if len == 0 {
+            break;
+        }
+        changed += decode_line(&line, at, writer, logger)?;
+        at.offset += len;
+        at.line += 1;
+    }
+    Ok(changed)
+}
+
+/// Where a line starts in the input.
+#[derive(Clone, Copy)]
+struct LineStart {
+    offset: usize,
+    line: usize,
+}
+
+fn decode_line<W: Write>(
+    line: &[u8],
+    at: LineStart,
+    writer: &mut W,
+    logger: &mut impl Recorder,
+) -> io::Result<usize> {
+    // "METHOD target PROTOCOL"
+    let Some((req_start, req_end)) = quoted_field(line, 0) else {
+        writer.write_all(line)?;
+        return Ok(0);
+    };
+    let request = &line[req_start..req_end];
+    writer.write_all(&line[..req_start])?;
+    let mut changed = 0;
+    match (memchr(b' ', request), memrchr(b' ', request)) {
+        (Some(first), Some(last)) if first < last => {
+            writer.write_all(&request[..=first])?;
+            changed += decode_field(
+                line,
+                req_start + first + 1..req_start + last,
+                at,
+                writer,
+                logger,
+            )?;
+            writer.write_all(&request[last..])?;
+        }
+        _ => writer.write_all(request)?,
+    }
+
+    // status and size, then "referer" in Combined Log Format
+    let Some((ref_start, ref_end)) = quoted_field(line, req_end + 1) else {
+        writer.write_all(&line[req_end..])?;
+        return Ok(changed);
+    };
+    writer.write_all(&line[req_end..ref_start])?;
+    changed += decode_field(line, ref_start..ref_end, at, writer, logger)?;
+    writer.write_all(&line[ref_end..])?;
+    Ok(changed)
+}
+
+/// Content range of the first `"`-quoted field at or after `from`.
+#[inline]
+fn quoted_field(line: &[u8], from: usize) -> Option<(usize, usize)> {
+    let start = from + memchr(b'"', line.get(from..)?)? + 1;
+    let end = start + memchr(b'"', &line[start..])?;
+    Some((start, end))
+}
+
+/// Decode the `range` field of `line` with
+/// [`decode_inner`](crate::decode_inner), keeping escaped control characters,
+/// which would break the line, as written. Returns how many segments between
+/// those changed.
+fn decode_field<W: Write>(
+    line: &[u8],
+    range: Range<usize>,
+    at: LineStart,
+    writer: &mut W,
+    logger: &mut impl Recorder,
+) -> io::Result<usize> {
+    let mut decode_segment = |start: usize, end: usize, writer: &mut W| {
+        if logger.enabled() {
+            let column_utf16 = utf16_len(&line[..start]) + 1;
+            logger.locate(at.offset + start, at.line, start + 1, column_utf16);
+        }
+        crate::decode_inner::<true, W>(&line[start..end], writer, Some(b'"'), logger)
+            .map(usize::from)
+    };
+    let end = range.end;
+    let mut changed = 0;
+    let mut i = range.start;
+    let mut segment_start = i;
+    while let Some(offset) = memchr(b'%', &line[i..end]) {
+        i += offset;
+        let is_control = i + 2 < end
+            && decode_hex_pair(line[i + 1], line[i + 2]).is_some_and(|b| b.is_ascii_control());
+        if is_control {
+            changed += decode_segment(segment_start, i, writer)?;
+            writer.write_all(&line[i..i + 3])?;
+            i += 3;
+            segment_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    changed += decode_segment(segment_start, end, writer)?;
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_access_log() {
+        let log = "1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] \"GET /s?q=%E4%B8%AD%20a%0A%22 HTTP/1.1\" 200 2326 \"https://x.com/%E6%96%87\" \"curl/8.0 %E4\"\n\
+                   1.2.3.4 - - [10/Oct/2000:13:55:37 -0700] \"GET /%E6%96%87 HTTP/1.1\" 404 0\n\
+                   not a log line %E4%B8%AD\n\
+                   \"-\" 400 0 \"-\"";
+        let expected = "1.2.3.4 - - [10/Oct/2000:13:55:36 -0700] \"GET /s?q=中%20a%0A%22 HTTP/1.1\" 200 2326 \"https://x.com/文\" \"curl/8.0 %E4\"\n\
+                        1.2.3.4 - - [10/Oct/2000:13:55:37 -0700] \"GET /文 HTTP/1.1\" 404 0\n\
+                        not a log line %E4%B8%AD\n\
+                        \"-\" 400 0 \"-\"";
+        let mut out = Vec::new();
+        let mut changes = Vec::new();
+        let changed = decode_access_log(log.as_bytes(), &mut out, &mut changes).unwrap();
+        assert_eq!(
+            (std::str::from_utf8(&out).unwrap(), changed),
+            (expected, true)
+        );
+        let located: Vec<_> = changes.iter().map(|c| (c.line, c.column)).collect();
+        assert_eq!(located, [(1, 47), (1, 92), (2, 47)]);
+        assert_eq!(changes[2].offset, log.find("GET /%E6").unwrap() + 4);
+    }
+}
@@ -0,0 +1,310 @@
+//! Decode many files in parallel.
+//!
+//! ```no_run
+//! use urldecoder::{
+//!     DecodeOptions,
+//!     batch::{BatchDecoder, expand_globs},
+//! };
+//!
+//! let paths = expand_globs(&["docs/**/*.md"], &["docs/node_modules".into()]).unwrap();
+//! let options = DecodeOptions::default();
+//! let summary = BatchDecoder::new(&options)
+//!     .dry_run(true)
+//!     .on_progress(|p| eprintln!("[{}/{}] {}", p.done, p.total, p.path.display()))
+//!     .decode_paths(&paths);
+//! println!("{} files would change", summary.totals().changed);
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use glob::glob;
+use rayon::{ThreadPool, prelude::*};
+use serde::Serialize;
+use snafu::ResultExt;
+
+use crate::{
+    DecodeOptions, Outcome, PatternSnafu, Result, decode_file_with,
+    log::{DecodeLogger, NoOpLogger},
+};
+
+/// Files matching any of the glob `patterns`, leaving out those under a path
+/// of `exclude`. Directories and unreadable entries are left out too.
+pub fn expand_globs(patterns: &[impl AsRef<str>], exclude: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        for path in glob(pattern).context(PatternSnafu { pattern })?.flatten() {
+            if path.is_file() && !in_exclude(exclude, &path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+#[inline]
+fn in_exclude(exclude: &[PathBuf], path: &Path) -> bool {
+    exclude.iter().any(|p| path.starts_with(p) || path == p)
+}
+
+/// A file done, see [`BatchDecoder::on_progress`].
+#[derive(Debug)]
+pub struct Progress<'a> {
+    pub path: &'a Path,
+    pub result: &'a Result<Outcome>,
+    /// How many files are done, this one included.
+    pub done: usize,
+    pub total: usize,
+}
+
+type ProgressFn<'a> = dyn Fn(&Progress<'_>) + Sync + 'a;
+
+/// Decodes files in parallel on a rayon thread pool.
+pub struct BatchDecoder<'a> {
+    options: &'a DecodeOptions,
+    dry_run: bool,
+    pool: Option<&'a ThreadPool>,
+    cancel: Option<&'a AtomicBool>,
+    progress: Option<Box<ProgressFn<'a>>>,
+}
+
+impl<'a> BatchDecoder<'a> {
+    pub fn new(options: &'a DecodeOptions) -> Self {
+        Self {
+            options,
+            dry_run: false,
+            pool: None,
+            cancel: None,
+            progress: None,
+        }
+    }
+
+    /// Find what would change without writing files.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Run on `pool` instead of the global rayon pool.
+    pub fn thread_pool(mut self, pool: &'a ThreadPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Stop starting files once `flag` is set. Files already started are
+    /// finished.
+    pub fn cancel_flag(mut self, flag: &'a AtomicBool) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    /// Call `f` after each file, from the thread that decoded it.
+    pub fn on_progress(mut self, f: impl Fn(&Progress<'_>) + Sync + 'a) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Decode `paths`.
+    pub fn decode_paths(&self, paths: &[PathBuf]) -> Summary {
+        self.decode_paths_with(paths, |_| NoOpLogger)
+    }
+
+    /// Decode `paths`, reporting the changes to each file to a logger made by
+    /// `new_logger`.
+    pub fn decode_paths_with<L, F>(&self, paths: &[PathBuf], new_logger: F) -> Summary<L>
+    where
+        L: DecodeLogger + Send,
+        F: Fn(&Path) -> L + Sync,
+    {
+        let done = AtomicUsize::new(0);
+        let cancelled = || self.cancel.is_some_and(|c| c.load(Ordering::Relaxed));
+        let run = || {
+            paths
+                .par_iter()
+                .filter_map(|path| {
+                    if cancelled() {
+                        return None;
+                    }
+                    let mut logger = new_logger(path);
+                    let result = decode_file_with(path, self.options, self.dry_run, &mut logger);
+                    if let Some(progress) = &self.progress {
+                        progress(&Progress {
+                            path,
+                            result: &result,
+                            done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                            total: paths.len(),
+                        });
+                    }
+                    Some(FileReport {
+                        path: path.clone(),
+                        result,
+                        logger,
+                    })
+                })
+                .collect()
+        };
+        let files: Vec<_> = match self.pool {
+            Some(pool) => pool.install(run),
+            None => run(),
+        };
+        Summary {
+            cancelled: files.len() < paths.len(),
+            dry_run: self.dry_run,
+            files,
+        }
+    }
+}
+
+/// How decoding one file went.
+#[derive(Debug)]
+pub struct FileReport<L = NoOpLogger> {
+    pub path: PathBuf,
+    pub result: Result<Outcome>,
+    /// The logger the changes went to.
+    pub logger: L,
+}
+
+/// What a [`BatchDecoder`] did.
+#[derive(Debug)]
+pub struct Summary<L = NoOpLogger> {
+    /// The files decoded, in input order.
+    pub files: Vec<FileReport<L>>,
+    /// Whether cancellation left some files out.
+    pub cancelled: bool,
+    /// Whether the changes were left unwritten, see
+    /// [`BatchDecoder::dry_run`].
+    pub dry_run: bool,
+}
+
+impl<L> Summary<L> {
+    pub fn totals(&self) -> Totals {
+        let mut totals = Totals::default();
+        for file in &self.files {
+            match &file.result {
+                Ok(Outcome::Unchanged) => totals.unchanged += 1,
+                Ok(Outcome::Changed { urls, bytes_saved }) => {
+                    totals.changed += 1;
+                    totals.urls += urls;
+                    totals.bytes_saved += bytes_saved;
+                }
+                Ok(Outcome::Skipped(_)) => totals.skipped += 1,
+                Err(_) => totals.failed += 1,
+            }
+        }
+        totals
+    }
+
+    /// The files that failed, with their errors.
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &crate::Error)> {
+        self.files
+            .iter()
+            .filter_map(|f| f.result.as_ref().err().map(|e| (f.path.as_path(), e)))
+    }
+}
+
+/// File counts and sums over a [`Summary`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    pub unchanged: usize,
+    pub changed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// URLs changed over all files.
+    pub urls: usize,
+    pub bytes_saved: i64,
+}
+
+impl Totals {
+    /// Files decoded without error, skipped ones included.
+    pub fn processed(&self) -> usize {
+        self.unchanged + self.changed + self.skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::log::Change;
+
+    #[test]
+    fn test_in_exclude() {
+        let pattern = PathBuf::from("path/to/file.txt");
+
+        // Case 1: Empty exclude should always return false
+        let exclude: Vec<PathBuf> = Vec::new();
+        assert!(!in_exclude(&exclude, &pattern));
+
+        // Case 2: Single path in exclude that matches the pattern
+        let exclude: Vec<PathBuf> = vec![PathBuf::from("path/to")];
+        assert!(in_exclude(&exclude, &pattern));
+
+        // Case 3: Single path in exclude that doesn't match the pattern
+        let exclude: Vec<PathBuf> = vec![PathBuf::from("other/path")];
+        assert!(!in_exclude(&exclude, &pattern));
+
+        // Case 4: Multiple paths in exclude, one of them matches the pattern
+        let exclude: Vec<PathBuf> = vec![PathBuf::from("path/to"), PathBuf::from("some/other")];
+        assert!(in_exclude(&exclude, &pattern));
+
+        // Case 5: Multiple paths in exclude, none of them matches the pattern
+        let exclude: Vec<PathBuf> = vec![PathBuf::from("/other/path"), PathBuf::from("some/other")];
+        assert!(!in_exclude(&exclude, &pattern));
+
+        // Case 6: Do not except files that only match prefix
+        let exclude: Vec<PathBuf> = vec![PathBuf::from("fi")];
+        let pattern = PathBuf::from("file.txt");
+        assert!(!in_exclude(&exclude, &pattern));
+    }
+
+    #[test]
+    fn test_batch_decoder() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        fs::write(&a, "https://x.com/%E4%B8%AD").unwrap();
+        fs::write(&b, "plain").unwrap();
+        let paths = vec![a.clone(), b, dir.path().join("missing.txt")];
+
+        let options = DecodeOptions::default();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let done = AtomicUsize::new(0);
+        let summary = BatchDecoder::new(&options)
+            .dry_run(true)
+            .thread_pool(&pool)
+            .on_progress(|p| {
+                assert_eq!(p.total, 3);
+                done.fetch_add(1, Ordering::Relaxed);
+            })
+            .decode_paths_with(&paths, |_| Vec::<Change>::new());
+        assert_eq!(done.load(Ordering::Relaxed), 3);
+        assert!(!summary.cancelled);
+        assert_eq!(
+            summary.totals(),
+            Totals {
+                unchanged: 1,
+                changed: 1,
+                failed: 1,
+                urls: 1,
+                bytes_saved: 6,
+                ..Default::default()
+            }
+        );
+        assert_eq!(summary.files[0].logger[0].decoded, "https://x.com/中");
+        assert_eq!(summary.errors().count(), 1);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "https://x.com/%E4%B8%AD");
+
+        let cancel = AtomicBool::new(true);
+        let summary = BatchDecoder::new(&options)
+            .cancel_flag(&cancel)
+            .decode_paths(&paths);
+        assert!(summary.cancelled);
+        assert!(summary.files.is_empty());
+    }
+}
@@ -0,0 +1,401 @@
+use std::path::Path;
+
+use memchr::{memchr, memchr3, memmem};
+
+/// Comment markers of a language, used to decode URLs only inside comments.
+///
+/// This is plain text scanning, not parsing. Comment markers inside string
+/// literals of [`quotes`](Self::quotes) and character literals of
+/// [`chars`](Self::chars) are skipped, but other literals, such as Python's
+/// `"""` or Rust's raw strings, are not recognized. A `'` right after a
+/// letter or digit is an apostrophe, as in `don't`, unless it follows a string
+/// prefix such as Python's `f` or `rb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommentSyntax {
+    /// Markers that start a comment running to the end of the line.
+    pub line: &'static [&'static [u8]],
+    /// `(open, close)` pairs of block comments.
+    pub block: &'static [(&'static [u8], &'static [u8])],
+    /// Quotes of string literals, which end at the same quote or the end of
+    /// the line and may hold backslash escapes. Comment markers inside them
+    /// are text.
+    pub quotes: &'static [u8],
+    /// Quotes of character literals, such as `'x'` or `'\n'`. Elsewhere these
+    /// bytes are text, such as Rust lifetimes.
+    pub chars: &'static [u8],
+}
+
+impl CommentSyntax {
+    /// No comments at all.
+    pub const NONE: Self = Self {
+        line: &[],
+        block: &[],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `//` and `/* */`: C, C++, Java, JavaScript, Go...
+    pub const C: Self = Self {
+        line: &[b"//"],
+        block: &[(b"/*", b"*/")],
+        quotes: b"\"'",
+        chars: b"",
+    };
+    /// `//` and `/* */` with `'` quoting only characters, not lifetimes: Rust.
+    pub const RUST: Self = Self {
+        line: &[b"//"],
+        block: &[(b"/*", b"*/")],
+        quotes: b"\"",
+        chars: b"'",
+    };
+    /// `/* */` only: CSS.
+    pub const CSS: Self = Self {
+        line: &[],
+        block: &[(b"/*", b"*/")],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `#`: shell, Python, Ruby, TOML, YAML...
+    pub const HASH: Self = Self {
+        line: &[b"#"],
+        block: &[],
+        quotes: b"\"'",
+        chars: b"",
+    };
+    /// `#`, `//` and `/* */`: PHP, HCL.
+    pub const HASH_C: Self = Self {
+        line: &[b"//", b"#"],
+        block: &[(b"/*", b"*/")],
+        quotes: b"\"'",
+        chars: b"",
+    };
+    /// `#` and `/* */`: Nix.
+    pub const NIX: Self = Self {
+        line: &[b"#"],
+        block: &[(b"/*", b"*/")],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `<!-- -->`: HTML, XML, Markdown.
+    pub const HTML: Self = Self {
+        line: &[],
+        block: &[(b"<!--", b"-->")],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `--`: SQL, Ada.
+    pub const SQL: Self = Self {
+        line: &[b"--"],
+        block: &[(b"/*", b"*/")],
+        quotes: b"\"'",
+        chars: b"",
+    };
+    /// `--` and `--[[ ]]`: Lua.
+    pub const LUA: Self = Self {
+        line: &[b"--"],
+        block: &[(b"--[[", b"]]")],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `--` and `{- -}`: Haskell, Elm.
+    pub const HASKELL: Self = Self {
+        line: &[b"--"],
+        block: &[(b"{-", b"-}")],
+        quotes: b"",
+        chars: b"",
+    };
+    /// `;` and `#`: INI.
+    pub const INI: Self = Self {
+        line: &[b";", b"#"],
+        block: &[],
+        quotes: b"",
+        chars: b"",
+    };
+
+    /// Get comment syntax by file extension, case insensitive.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let syntax = match ext.to_ascii_lowercase().as_str() {
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" | "cs" | "java" | "kt"
+            | "kts" | "scala" | "groovy" | "gradle" | "go" | "swift" | "dart" | "zig" | "js"
+            | "mjs" | "cjs" | "jsx" | "ts" | "mts" | "cts" | "tsx" | "proto" | "scss" | "less"
+            | "jsonc" | "json5" => Self::C,
+            "rs" => Self::RUST,
+            "css" => Self::CSS,
+            "py" | "pyi" | "sh" | "bash" | "zsh" | "fish" | "rb" | "pl" | "pm" | "r" | "toml"
+            | "yaml" | "yml" | "conf" | "cfg" | "cmake" | "ps1" | "dockerfile" | "mk" => Self::HASH,
+            "php" | "tf" | "hcl" => Self::HASH_C,
+            "nix" => Self::NIX,
+            "html" | "htm" | "xhtml" | "xml" | "svg" | "md" | "markdown" | "vue" => Self::HTML,
+            "sql" | "ada" | "adb" | "ads" => Self::SQL,
+            "lua" => Self::LUA,
+            "hs" | "elm" => Self::HASKELL,
+            "ini" => Self::INI,
+            _ => return None,
+        };
+        Some(syntax)
+    }
+
+    /// Get comment syntax by file extension, falling back to well-known file
+    /// names such as `Makefile`.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+        if let Some(syntax) = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+        {
+            return Some(syntax);
+        }
+        match path.file_name()?.to_str()? {
+            "Makefile" | "makefile" | "GNUmakefile" | "Dockerfile" | "Containerfile"
+            | "CMakeLists.txt" | ".gitignore" | ".gitattributes" | ".editorconfig" | ".env" => {
+                Some(Self::HASH)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Code,
+    Line,
+    Block(&'static [u8]),
+    Str(u8),
+}
+
+/// Tracks whether a position of the input is inside a comment.
+///
+/// Positions must be queried in increasing order, and each queried range must
+/// not have been overwritten yet.
+pub(crate) struct CommentTracker {
+    syntax: CommentSyntax,
+    /// First bytes of all openers, to skip plain text quickly.
+    open_first: [bool; 256],
+    pos: usize,
+    state: State,
+}
+
+impl CommentTracker {
+    pub(crate) fn new(syntax: CommentSyntax) -> Self {
+        let mut open_first = [false; 256];
+        for open in syntax
+            .line
+            .iter()
+            .chain(syntax.block.iter().map(|(open, _)| open))
+        {
+            if let Some(&b) = open.first() {
+                open_first[b as usize] = true;
+            }
+        }
+        for &quote in syntax.quotes.iter().chain(syntax.chars) {
+            open_first[quote as usize] = true;
+        }
+        Self {
+            syntax,
+            open_first,
+            pos: 0,
+            state: State::Code,
+        }
+    }
+
+    /// Whether the URL at `data[start..end]` is inside a comment.
+    pub(crate) fn url_in_comment(&mut self, data: &[u8], start: usize, end: usize) -> bool {
+        self.advance(data, start);
+        let in_comment = matches!(self.state, State::Line | State::Block(_));
+
+        // URL bytes never open a comment (`//` in `http://`), but may close one
+        // (`-->` right after a URL).
+        if let State::Block(close) = self.state {
+            let window_end = (end + close.len().saturating_sub(1)).min(data.len());
+            if let Some(idx) = memmem::find(&data[start..window_end], close) {
+                self.pos = start + idx + close.len();
+                self.state = State::Code;
+                if self.pos < end {
+                    self.advance(data, end);
+                }
+                return in_comment;
+            }
+        }
+        self.pos = end;
+        in_comment
+    }
+
+    fn advance(&mut self, data: &[u8], to: usize) {
+        let mut i = self.pos;
+        while i < to {
+            match self.state {
+                State::Code => match self.find_open(&data[..to], i) {
+                    Some((idx, state)) => {
+                        i = idx;
+                        self.state = state;
+                    }
+                    None => i = to,
+                },
+                State::Line => match memchr(b'\n', &data[i..to]) {
+                    Some(idx) => {
+                        i += idx + 1;
+                        self.state = State::Code;
+                    }
+                    None => i = to,
+                },
+                State::Block(close) => match memmem::find(&data[i..to], close) {
+                    Some(idx) => {
+                        i += idx + close.len();
+                        self.state = State::Code;
+                    }
+                    None => i = to,
+                },
+                State::Str(quote) => match memchr3(quote, b'\\', b'\n', &data[i..to]) {
+                    Some(idx) => {
+                        i += idx + 1;
+                        if data[i - 1] == b'\\' {
+                            // the escaped byte may lie past `to`
+                            i += 1;
+                        } else {
+                            self.state = State::Code;
+                        }
+                    }
+                    None => i = to,
+                },
+            }
+        }
+        self.pos = i;
+    }
+
+    /// Find the next comment opener at or after `from`, returning the position
+    /// right after it.
+    fn find_open(&self, data: &[u8], from: usize) -> Option<(usize, State)> {
+        let mut i = from;
+        while i < data.len() {
+            let b = data[i];
+            i += 1;
+            if !self.open_first[b as usize] || (b == b'\'' && is_apostrophe(data, i - 1)) {
+                continue;
+            }
+            if self.syntax.quotes.contains(&b) {
+                return Some((i, State::Str(b)));
+            }
+            let rest = &data[i - 1..];
+            if self.syntax.chars.contains(&b) {
+                i += char_literal_len(rest).map_or(0, |len| len - 1);
+                continue;
+            }
+            // block openers first: `--[[` must win over `--`
+            for (open, close) in self.syntax.block {
+                if rest.starts_with(open) {
+                    return Some((i - 1 + open.len(), State::Block(close)));
+                }
+            }
+            for open in self.syntax.line {
+                if rest.starts_with(open) {
+                    return Some((i - 1 + open.len(), State::Line));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Whether the `'` at `data[i]` follows a word that is not a string prefix.
+#[inline]
+fn is_apostrophe(data: &[u8], i: usize) -> bool {
+    let word = data[..i]
+        .iter()
+        .rev()
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .count();
+    word > 0 && !(word <= 2 && data[i - word..i].iter().all(|b| b"bBfFrRuU".contains(b)))
+}
+
+/// Length of the character literal at the start of `rest`, such as `'x'`,
+/// `'中'` or `'\u{1F600}'`.
+fn char_literal_len(rest: &[u8]) -> Option<usize> {
+    let quote = rest[0];
+    let body = &rest[1..];
+    let len = match *body.first()? {
+        b'\\' => {
+            // `\u{10FFFF}` is the longest escape
+            let tail = body.get(2..)?;
+            2 + memchr(quote, &tail[..tail.len().min(9)])?
+        }
+        b if b == quote || b == b'\n' => return None,
+        0x00..0x80 => 1,
+        0xC0..0xE0 => 2,
+        0xE0..0xF0 => 3,
+        0xF0.. => 4,
+        _ => return None,
+    };
+    (body.get(len) == Some(&quote)).then_some(len + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            CommentSyntax::from_path("src/main.RS"),
+            Some(CommentSyntax::RUST)
+        );
+        assert_eq!(
+            CommentSyntax::from_path("a/Makefile"),
+            Some(CommentSyntax::HASH)
+        );
+        assert_eq!(
+            CommentSyntax::from_path("README.md"),
+            Some(CommentSyntax::HTML)
+        );
+        assert_eq!(CommentSyntax::from_path("data.bin"), None);
+    }
+
+    #[test]
+    fn test_tracker() {
+        let text = b"a /* b */ c -- d\ne <!-- f --> g";
+        let mut tracker = CommentTracker::new(CommentSyntax::C);
+        assert!(!tracker.url_in_comment(text, 0, 1));
+        assert!(tracker.url_in_comment(text, 5, 6));
+        assert!(!tracker.url_in_comment(text, 10, 11));
+
+        let mut tracker = CommentTracker::new(CommentSyntax::SQL);
+        assert!(tracker.url_in_comment(text, 15, 16));
+        assert!(!tracker.url_in_comment(text, 18, 19));
+
+        let mut tracker = CommentTracker::new(CommentSyntax::HTML);
+        assert!(tracker.url_in_comment(text, 25, 26));
+        assert!(!tracker.url_in_comment(text, 30, 31));
+    }
+
+    #[test]
+    fn test_tracker_strings() {
+        let text = br#"glob("src/*.rs", '\'/*') x
+"a\" # b" // c
+'unterminated /*
+d"#;
+        let mut tracker = CommentTracker::new(CommentSyntax::C);
+        assert!(!tracker.url_in_comment(text, 25, 26));
+        assert!(tracker.url_in_comment(text, 39, 40));
+        assert!(!tracker.url_in_comment(text, 59, 60));
+
+        let mut tracker = CommentTracker::new(CommentSyntax::HASH);
+        assert!(!tracker.url_in_comment(text, 31, 32));
+        assert!(!tracker.url_in_comment(text, 39, 40));
+    }
+
+    #[test]
+    fn test_tracker_apostrophes() {
+        let text = br#"fn f<'a>(x: &'a str) -> char { '"' } // c
+let b = b'/'; '\u{1F600}'; '\'' // d
+title: Don't # e
+print(f'# {x}', rb'#') # g"#;
+        let pos = |s: &str| memmem::find(text, s.as_bytes()).unwrap();
+        let mut tracker = CommentTracker::new(CommentSyntax::RUST);
+        assert!(tracker.url_in_comment(text, pos("c\n"), pos("c\n") + 1));
+        assert!(tracker.url_in_comment(text, pos("d\n"), pos("d\n") + 1));
+
+        let mut tracker = CommentTracker::new(CommentSyntax::HASH);
+        assert!(tracker.url_in_comment(text, pos(" e") + 1, pos(" e") + 2));
+        assert!(!tracker.url_in_comment(text, pos("{x}"), pos("{x}") + 1));
+        assert!(tracker.url_in_comment(text, pos(" g") + 1, pos(" g") + 2));
+    }
+}
@@ -0,0 +1,218 @@
+use memchr::memmem::Finder;
+
+use crate::{DecodeOptions, SchemeBoundary};
+
+/// A literal that may start a URL.
+struct Pattern {
+    finder: Finder<'static>,
+    /// Position of the next occurrence, valid while it is not before the
+    /// search start.
+    next: Option<usize>,
+}
+
+impl Pattern {
+    fn new(data: &[u8], needle: &'static [u8]) -> Self {
+        let finder = Finder::new(needle);
+        let next = finder.find(data);
+        Self { finder, next }
+    }
+
+    #[inline(always)]
+    fn next_from(&mut self, data: &[u8], from: usize) -> Option<usize> {
+        if self.next.is_some_and(|at| at < from) {
+            self.next = self.finder.find(&data[from..]).map(|idx| from + idx);
+        }
+        self.next
+    }
+}
+
+/// Finds where URLs start: `http://` and `https://`, and optionally `file://`,
+/// scheme-relative (`//host/...`) and bare `www.` URLs.
+///
+/// Searches must be done in increasing order, and `data` must not have been
+/// overwritten from the search start on.
+pub(crate) struct UrlFinder {
+    http: Pattern,
+    file: Option<Pattern>,
+    scheme_relative: Option<Pattern>,
+    www: Option<Pattern>,
+    boundary: SchemeBoundary,
+}
+
+impl UrlFinder {
+    pub(crate) fn new(data: &[u8], options: &DecodeOptions) -> Self {
+        Self {
+            http: Pattern::new(data, b"http"),
+            file: options.file_urls.then(|| Pattern::new(data, b"file://")),
+            scheme_relative: options
+                .detect_scheme_relative
+                .then(|| Pattern::new(data, b"//")),
+            www: options.detect_www.then(|| Pattern::new(data, b"www.")),
+            boundary: options.scheme_boundary,
+        }
+    }
+
+    /// Find the next URL at or after `from`. Returns its start and the length
+    /// of its prefix, which the URL characters follow.
+    #[inline(always)]
+    pub(crate) fn find(&mut self, data: &[u8], from: usize) -> Option<(usize, usize)> {
+        let mut search = from;
+        loop {
+            let http = self.http.next_from(data, search);
+            let file = self.file.as_mut().and_then(|p| p.next_from(data, search));
+            let slashes = self
+                .scheme_relative
+                .as_mut()
+                .and_then(|p| p.next_from(data, search));
+            let www = self.www.as_mut().and_then(|p| p.next_from(data, search));
+            let at = [http, file, slashes, www].into_iter().flatten().min()?;
+
+            // the byte before `from` may be overwritten; it ends the previous URL,
+            // so it is a boundary anyway
+            let prev = (at > from).then(|| data[at - 1]);
+            let rest = &data[at..];
+            let prefix_len = if http == Some(at) {
+                http_prefix(rest).filter(|_| self.boundary.allows(prev))
+            } else if file == Some(at) {
+                Some(7).filter(|_| self.boundary.allows(prev))
+            } else if slashes == Some(at) {
+                scheme_relative_prefix(prev, rest)
+            } else {
+                www_prefix(prev, rest)
+            };
+            if let Some(prefix_len) = prefix_len {
+                return Some((at, prefix_len));
+            }
+            search = at + 1;
+        }
+    }
+}
+
+#[inline(always)]
+fn http_prefix(rest: &[u8]) -> Option<usize> {
+    if rest.starts_with(b"https://") {
+        Some(8)
+    } else if rest.starts_with(b"http://") {
+        Some(7)
+    } else {
+        None
+    }
+}
+
+/// `//host/...`, as in `src="//cdn.example.com/a.png"`.
+fn scheme_relative_prefix(prev: Option<u8>, rest: &[u8]) -> Option<usize> {
+    if !is_word_boundary(prev) {
+        return None;
+    }
+    host_len(&rest[2..]).map(|len| 2 + len)
+}
+
+/// `www.host/...`.
+fn www_prefix(prev: Option<u8>, rest: &[u8]) -> Option<usize> {
+    if !is_word_boundary(prev) {
+        return None;
+    }
+    host_len(rest)
+}
+
+/// Whether a URL without scheme may start after `prev`, so that a path like
+/// `a//b.com/` or an email like `me@www.x.com` is not taken as a URL.
+#[inline]
+fn is_word_boundary(prev: Option<u8>) -> bool {
+    prev.is_none_or(|b| !b.is_ascii_alphanumeric() && !b"-_.:/\\@%+~".contains(&b))
+}
+
+/// Length of the domain name at the start of `s`, if followed by a path, query,
+/// fragment or port. A URL without any of them has nothing to decode, and
+/// requiring one keeps prose like `//TODO.` out.
+fn host_len(s: &[u8]) -> Option<usize> {
+    let len = s
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.'))
+        .count();
+    let host = &s[..len];
+    if !matches!(s.get(len), Some(b'/' | b'?' | b'#' | b':'))
+        || host.first().is_none_or(|b| !b.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    let tld = &host[host.iter().rposition(|&b| b == b'.')? + 1..];
+    (tld.len() >= 2 && tld.iter().all(u8::is_ascii_alphabetic)).then_some(len)
+}
+
+/// Split the scheme of `url`. Returns the scheme, empty for a scheme-relative
+/// or bare URL, and where the authority starts.
+pub(crate) fn split_scheme(url: &[u8]) -> (&[u8], usize) {
+    let scheme_len = url
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+        .count();
+    if url[scheme_len..].starts_with(b"://") {
+        (&url[..scheme_len], scheme_len + 3)
+    } else if url.starts_with(b"//") {
+        (&[], 2)
+    } else {
+        (&[], 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let options = DecodeOptions {
+            detect_scheme_relative: true,
+            detect_www: true,
+            ..Default::default()
+        };
+        let data =
+            b"// TODO.fix x//a.com/ me@www.b.com/ httpx (//cdn.c.io/a) www.d.org?q=1 www.e.x1/";
+        let mut finder = UrlFinder::new(data, &options);
+        let mut found = Vec::new();
+        let mut from = 0;
+        while let Some((start, prefix_len)) = finder.find(data, from) {
+            found.push(&data[start..start + prefix_len]);
+            from = start + prefix_len;
+        }
+        assert_eq!(found, [&b"//cdn.c.io"[..], b"www.d.org"]);
+    }
+
+    #[test]
+    fn test_scheme_boundary() {
+        let data = "http://a xhttp://b git+https://c (https://d 见http://e".as_bytes();
+        let find_all = |boundary| {
+            let options = DecodeOptions {
+                scheme_boundary: boundary,
+                ..Default::default()
+            };
+            let mut finder = UrlFinder::new(data, &options);
+            let mut found = Vec::new();
+            let mut from = 0;
+            while let Some((start, prefix_len)) = finder.find(data, from) {
+                found.push(data[start + prefix_len]);
+                from = start + prefix_len;
+            }
+            found
+        };
+        assert_eq!(find_all(SchemeBoundary::Any), b"abcde");
+        assert_eq!(find_all(SchemeBoundary::Word), b"ade");
+        assert_eq!(find_all(SchemeBoundary::Strict), b"ade");
+
+        let data = b"x=http://a,https://b";
+        let options = DecodeOptions {
+            scheme_boundary: SchemeBoundary::Strict,
+            ..Default::default()
+        };
+        let mut finder = UrlFinder::new(data, &options);
+        assert_eq!(finder.find(data, 0), Some((2, 7)));
+    }
+
+    #[test]
+    fn test_split_scheme() {
+        assert_eq!(split_scheme(b"HTTP://a/b"), (&b"HTTP"[..], 7));
+        assert_eq!(split_scheme(b"//a/b"), (&b""[..], 2));
+        assert_eq!(split_scheme(b"www.a.com/?u=http://b"), (&b""[..], 0));
+    }
+}
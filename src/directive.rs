@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+use memchr::{memchr, memmem::Finder};
+
+use crate::comment::CommentSyntax;
+
+const PREFIX: &[u8] = b"urldecoder-";
+const DISABLE_NEXT_LINE: &[u8] = b"disable-next-line";
+const DISABLE: &[u8] = b"disable";
+const ENABLE: &[u8] = b"enable";
+/// Comment openers of all [`CommentSyntax`] constants, for text of unknown
+/// syntax.
+const ANY_OPENER: &[&[u8]] = &[b"//", b"/*", b"#", b"<!--", b"--", b"--[[", b"{-", b";"];
+
+/// Tracks the in-file directives, like linters' suppression comments:
+///
+/// - `urldecoder-disable-next-line`: keep URLs on the next line
+/// - `urldecoder-disable`: keep URLs until `urldecoder-enable`
+/// - `urldecoder-enable`
+///
+/// A directive is a whole word starting a comment: only blanks may separate it
+/// from a comment opener before it on its line, so prose mentioning a
+/// directive is not one. Without a known syntax, the openers of every
+/// [`CommentSyntax`] count. URLs must be queried in increasing order, and each
+/// queried range must not have been overwritten yet.
+pub(crate) struct DirectiveTracker {
+    finder: Finder<'static>,
+    syntax: Option<CommentSyntax>,
+    /// Position of the next directive prefix.
+    next: Option<usize>,
+    disabled: bool,
+    disabled_line: Range<usize>,
+}
+
+impl DirectiveTracker {
+    pub(crate) fn new(data: &[u8], syntax: Option<CommentSyntax>) -> Self {
+        let finder = Finder::new(PREFIX);
+        let next = finder.find(data);
+        Self {
+            finder,
+            syntax,
+            next,
+            disabled: false,
+            disabled_line: 0..0,
+        }
+    }
+
+    /// Whether the URL at `data[start..end]` may be decoded.
+    #[inline(always)]
+    pub(crate) fn url_enabled(&mut self, data: &[u8], start: usize, end: usize) -> bool {
+        if self.next.is_some_and(|at| at < end) {
+            self.apply_until(data, start, end);
+        }
+        !self.disabled && !self.disabled_line.contains(&start)
+    }
+
+    #[cold]
+    fn apply_until(&mut self, data: &[u8], start: usize, end: usize) {
+        while let Some(at) = self.next.filter(|&at| at < start) {
+            let rest = &data[at + PREFIX.len()..];
+            let comment = self.starts_comment(data, at);
+            if comment && is_word(rest, DISABLE_NEXT_LINE) {
+                let line_start = match memchr(b'\n', rest) {
+                    Some(idx) => at + PREFIX.len() + idx + 1,
+                    None => data.len(),
+                };
+                let line_end = match memchr(b'\n', &data[line_start..]) {
+                    Some(idx) => line_start + idx,
+                    None => data.len(),
+                };
+                self.disabled_line = line_start..line_end;
+            } else if comment && is_word(rest, DISABLE) {
+                self.disabled = true;
+            } else if comment && is_word(rest, ENABLE) {
+                self.disabled = false;
+            }
+            self.find_next(data, at + PREFIX.len());
+        }
+        // a directive-like text inside the URL itself is not a directive
+        if self.next.is_some_and(|at| at < end) {
+            self.find_next(data, end);
+        }
+    }
+
+    /// Whether only blanks separate `data[at..]` from a comment opener before
+    /// it.
+    fn starts_comment(&self, data: &[u8], at: usize) -> bool {
+        let mut i = at;
+        while i > 0 && matches!(data[i - 1], b' ' | b'\t') {
+            i -= 1;
+        }
+        let before = &data[..i];
+        match &self.syntax {
+            Some(syntax) => syntax
+                .line
+                .iter()
+                .chain(syntax.block.iter().map(|(open, _)| open))
+                .any(|open| before.ends_with(open)),
+            None => ANY_OPENER.iter().any(|open| before.ends_with(open)),
+        }
+    }
+
+    #[inline]
+    fn find_next(&mut self, data: &[u8], from: usize) {
+        self.next = self.finder.find(&data[from..]).map(|idx| from + idx);
+    }
+}
+
+/// Whether `rest` starts with the whole word `word`.
+#[inline]
+fn is_word(rest: &[u8], word: &[u8]) -> bool {
+    rest.starts_with(word)
+        && rest
+            .get(word.len())
+            .is_none_or(|&b| !(b.is_ascii_alphanumeric() || b == b'-' || b == b'_'))
+}
@@ -4,6 +4,7 @@ use snafu::Snafu;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
 pub enum Error {
     #[snafu(display("Failed to open input file {}: {}", path.display(), source))]
     OpenInput { path: PathBuf, source: io::Error },
@@ -29,8 +30,55 @@ pub enum Error {
     #[snafu(display("Failed to write back to original file {}: {}", path.display(), source))]
     WriteBack { path: PathBuf, source: io::Error },
 
+    #[snafu(display("Failed to read directory {}: {}", path.display(), source))]
+    ReadDir { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to rename {} to {}: {}", from.display(), to.display(), source))]
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        source: io::Error,
+    },
+
     #[snafu(display("Invalid UTF-8 sequence: {}", source))]
     InvalidUtf8 { source: simdutf8::basic::Utf8Error },
+
+    #[snafu(display("Invalid rewrite rule at line {}: {}", line, reason))]
+    InvalidRewriteRule { line: usize, reason: String },
+
+    #[cfg(feature = "regex")]
+    #[snafu(display("Invalid regex at line {}: {}", line, source))]
+    InvalidRegex { line: usize, source: regex::Error },
+
+    #[cfg(feature = "batch")]
+    #[snafu(display("Invalid glob pattern {}: {}", pattern, source))]
+    Pattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+}
+
+impl Error {
+    /// Name of the variant, such as `"OpenInput"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::OpenInput { .. } => "OpenInput",
+            Self::ReadInput { .. } => "ReadInput",
+            Self::Decode { .. } => "Decode",
+            Self::WriteOutput { .. } => "WriteOutput",
+            Self::CreateTemp { .. } => "CreateTemp",
+            Self::PersistTemp { .. } => "PersistTemp",
+            Self::WriteBack { .. } => "WriteBack",
+            Self::ReadDir { .. } => "ReadDir",
+            Self::Rename { .. } => "Rename",
+            Self::InvalidUtf8 { .. } => "InvalidUtf8",
+            Self::InvalidRewriteRule { .. } => "InvalidRewriteRule",
+            #[cfg(feature = "regex")]
+            Self::InvalidRegex { .. } => "InvalidRegex",
+            #[cfg(feature = "batch")]
+            Self::Pattern { .. } => "Pattern",
+        }
+    }
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
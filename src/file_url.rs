@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use crate::transform::{FILE_KEEP, Rules, decode_component, is_file_url};
+
+/// Decode a `file://` URL. `%2F` and `%00` stay encoded, as a slash or NUL
+/// inside a path segment is not a path separator or terminator.
+///
+/// Returns `None` if `url` is not a `file` URL or does not decode to UTF-8.
+///
+/// ```
+/// use urldecoder::decode_file_url;
+///
+/// assert_eq!(
+///     decode_file_url("file:///home/u/%E6%96%87%E6%A1%A3/a%2Fb.pdf").as_deref(),
+///     Some("file:///home/u/文档/a%2Fb.pdf")
+/// );
+/// ```
+pub fn decode_file_url(url: &str) -> Option<String> {
+    if !is_file_url(url.as_bytes()) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(url.len());
+    decode_component(url.as_bytes(), Rules::ALL.keeping(FILE_KEEP), &mut out);
+    String::from_utf8(out).ok()
+}
+
+/// Convert a local `file://` URL to an OS path, decoding each path segment.
+/// The query and fragment are ignored.
+///
+/// Returns `None` if `url` is not a `file` URL, names a host other than
+/// `localhost`, or has a segment that no path can hold: one with an encoded
+/// `/` or NUL, or, except on Unix, one that does not decode to UTF-8.
+///
+/// ```
+/// # #[cfg(unix)]
+/// # {
+/// use std::path::Path;
+///
+/// use urldecoder::file_url_to_path;
+///
+/// assert_eq!(
+///     file_url_to_path("file:///home/u/%E6%96%87%E6%A1%A3/a.pdf").as_deref(),
+///     Some(Path::new("/home/u/文档/a.pdf"))
+/// );
+/// assert_eq!(file_url_to_path("file:///home/u/a%2Fb.pdf"), None);
+/// # }
+/// ```
+pub fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    if !is_file_url(url.as_bytes()) {
+        return None;
+    }
+    let rest = &url[7..];
+    let rest = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+    let (host, path) = rest.split_at(rest.find('/')?);
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(path.len());
+    for (idx, segment) in path.split('/').enumerate() {
+        if idx > 0 {
+            bytes.push(b'/');
+        }
+        let start = bytes.len();
+        decode_component(segment.as_bytes(), Rules::ALL, &mut bytes);
+        if bytes[start..].iter().any(|&b| b == b'/' || b == 0) {
+            return None;
+        }
+    }
+    bytes_to_path(bytes)
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+    Some(OsString::from_vec(bytes).into())
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> Option<PathBuf> {
+    let path = String::from_utf8(bytes).ok()?;
+    // `/C:/dir` names drive `C:`
+    let b = path.as_bytes();
+    let path = if b.len() >= 3 && b[0] == b'/' && b[1].is_ascii_alphabetic() && b[2] == b':' {
+        &path[1..]
+    } else {
+        &path[..]
+    };
+    Some(path.replace('/', std::path::MAIN_SEPARATOR_STR).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_file_url() {
+        assert_eq!(
+            decode_file_url("FILE:///tmp/a%20b%2fc%00.txt?x=%E4%B8%AD").as_deref(),
+            Some("FILE:///tmp/a b%2fc%00.txt?x=中")
+        );
+        assert_eq!(decode_file_url("https://a.com/%E4%B8%AD"), None);
+        assert_eq!(decode_file_url("file:///%FF"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_url_to_path() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+        let cases = [
+            ("file:///tmp/a%20b.txt", Some(Path::new("/tmp/a b.txt"))),
+            ("file://localhost/tmp/x#top", Some(Path::new("/tmp/x"))),
+            ("file:///", Some(Path::new("/"))),
+            ("file://server/share/x", None),
+            ("file:///tmp/a%2Fb", None),
+            ("file:///tmp/a%00", None),
+            ("file://", None),
+            ("http://a.com/x", None),
+        ];
+        for (url, path) in cases {
+            assert_eq!(file_url_to_path(url).as_deref(), path, "{url}");
+        }
+        assert_eq!(
+            file_url_to_path("file:///tmp/%FF").unwrap(),
+            Path::new(OsStr::from_bytes(b"/tmp/\xFF"))
+        );
+    }
+}
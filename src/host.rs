@@ -0,0 +1,61 @@
+use memchr::{memchr3, memrchr};
+
+use crate::{DecodeOptions, detect::split_scheme};
+
+/// Whether the host of `url` passes [`DecodeOptions::only_hosts`] and
+/// [`DecodeOptions::skip_hosts`].
+#[inline]
+pub(crate) fn host_allowed(options: &DecodeOptions, url: &[u8]) -> bool {
+    if options.only_hosts.is_empty() && options.skip_hosts.is_empty() {
+        return true;
+    }
+    let host = url_host(url);
+    (options.only_hosts.is_empty() || options.only_hosts.iter().any(|p| host_matches(p, host)))
+        && !options.skip_hosts.iter().any(|p| host_matches(p, host))
+}
+
+/// Host of `url`, without userinfo and port.
+fn url_host(url: &[u8]) -> &[u8] {
+    let start = split_scheme(url).1;
+    let end = memchr3(b'/', b'?', b'#', &url[start..]).map_or(url.len(), |idx| start + idx);
+    let authority = &url[start..end];
+    let host = match memrchr(b'@', authority) {
+        Some(idx) => &authority[idx + 1..],
+        None => authority,
+    };
+    match memrchr(b':', host) {
+        Some(idx) if !host[idx..].contains(&b']') => &host[..idx],
+        _ => host,
+    }
+}
+
+/// Match a host against a pattern, either a host name or `*.` followed by a
+/// domain, matching its subdomains. Case insensitive.
+fn host_matches(pattern: &str, host: &[u8]) -> bool {
+    match pattern.as_bytes().strip_prefix(b"*.") {
+        Some(domain) => {
+            host.len() > domain.len() + 1
+                && host[host.len() - domain.len() - 1] == b'.'
+                && host[host.len() - domain.len()..].eq_ignore_ascii_case(domain)
+        }
+        None => host.eq_ignore_ascii_case(pattern.as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_matches() {
+        assert_eq!(url_host(b"https://u:p@Docs.RS:8080/a?b#c"), b"Docs.RS");
+        assert_eq!(url_host(b"http://x.com?a"), b"x.com");
+        assert_eq!(url_host(b"//x.com/a"), b"x.com");
+        assert_eq!(url_host(b"www.x.com/a?u=http://y.com"), b"www.x.com");
+        assert!(host_matches("docs.rs", b"Docs.RS"));
+        assert!(!host_matches("docs.rs", b"a.docs.rs"));
+        assert!(host_matches("*.example.com", b"a.b.example.com"));
+        assert!(!host_matches("*.example.com", b"example.com"));
+        assert!(!host_matches("*.example.com", b"badexample.com"));
+    }
+}
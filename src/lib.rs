@@ -1,22 +1,48 @@
+mod access_log;
+#[cfg(feature = "batch")]
+pub mod batch;
+pub mod comment;
+mod detect;
+mod directive;
 pub mod error;
+mod file_url;
+mod host;
 pub mod log;
+mod normalize;
+pub mod options;
+mod outcome;
+pub mod rename;
+#[cfg(feature = "batch")]
+pub mod report;
+pub mod rewrite;
+mod transform;
 
-#[cfg(feature = "verbose-log")]
-use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{
     fs,
     io::{self, BufWriter, Write},
     path::Path,
 };
 
+pub use access_log::decode_access_log;
+pub use comment::CommentSyntax;
 pub use error::*;
-use memchr::{memchr, memmem::Finder};
+pub use file_url::{decode_file_url, file_url_to_path};
+use memchr::{memchr, memchr3_iter, memrchr};
+pub use outcome::{Outcome, SkipReason};
+pub use rewrite::{RewriteRule, RewriteRules};
 use snafu::ResultExt;
 use tempfile::NamedTempFile;
 
-#[cfg(feature = "verbose-log")]
-use crate::log::VerboseLogger;
-use crate::log::{DecodeLogger, NoOpLogger};
+use crate::{
+    access_log::decode_access_log_counted,
+    comment::CommentTracker,
+    detect::UrlFinder,
+    directive::DirectiveTracker,
+    host::host_allowed,
+    log::{ChangeRecorder, DecodeLogger, LineTracker, NoOpRecorder, Recorder},
+    transform::Transformer,
+};
+pub use crate::{options::*, transform::nested_urls};
 
 const SMALL_FILE_THRESHOLD: u64 = 256 * 1024;
 const IO_BUF_SIZE: usize = 64 * 1024;
@@ -24,6 +50,7 @@ const URL_CHAR_BITMAP: [u32; 8] = gen_url_bitmap(b"-+&@#/%?=~_|!:,.;");
 const URL_END_CHAR_BITMAP: [u32; 8] = gen_url_bitmap(b"-+&@#/%=~_|");
 const HEX_MAP: [u8; 256] = gen_hex_map();
 const HEX_INVALID: u8 = 0xFF;
+const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
 
 const fn gen_url_bitmap(symbols: &[u8]) -> [u32; 8] {
     let mut bitmap = [0u32; 8];
@@ -93,6 +120,16 @@ fn decode_hex_pair(h1: u8, h2: u8) -> Option<u8> {
     }
 }
 
+/// Write `byte` as an escape with uppercase hex digits (RFC 3986 §6.2.2.1).
+#[inline(always)]
+fn push_escape(out: &mut Vec<u8>, byte: u8) {
+    out.extend_from_slice(&[
+        b'%',
+        HEX_UPPER[(byte >> 4) as usize],
+        HEX_UPPER[(byte & 0xF) as usize],
+    ]);
+}
+
 #[inline(always)]
 fn is_url_char(byte: u8) -> bool {
     let idx = byte as usize;
@@ -105,6 +142,50 @@ fn is_url_end_char(byte: u8) -> bool {
     unsafe { (URL_END_CHAR_BITMAP.get_unchecked(idx >> 5) >> (idx & 31)) & 1 == 1 }
 }
 
+/// Tracks the quote of the string literal a URL sits in, if any: the quote
+/// left open on the URL's line before it. Decoding that character would
+/// terminate the literal early.
+///
+/// URLs must be queried in increasing order, and the bytes from the end of
+/// the previous URL on must still be the input ones.
+struct QuoteTracker {
+    scanned: usize,
+    open: Option<u8>,
+}
+
+impl QuoteTracker {
+    fn new() -> Self {
+        Self {
+            scanned: 0,
+            open: None,
+        }
+    }
+
+    /// The quote enclosing the URL at `start..end` of `data`.
+    #[inline]
+    fn delimiter(&mut self, data: &[u8], start: usize, end: usize) -> Option<u8> {
+        let mut from = self.scanned;
+        if let Some(idx) = memrchr(b'\n', &data[from..start]) {
+            from += idx + 1;
+            self.open = None;
+        }
+        let text = &data[from..start];
+        for idx in memchr3_iter(b'"', b'\'', b'`', text) {
+            let backslashes = text[..idx].iter().rev().take_while(|&&b| b == b'\\');
+            if backslashes.count() % 2 == 1 {
+                continue;
+            }
+            match self.open {
+                None => self.open = Some(text[idx]),
+                Some(quote) if quote == text[idx] => self.open = None,
+                Some(_) => {}
+            }
+        }
+        self.scanned = end;
+        self.open
+    }
+}
+
 #[inline(always)]
 fn trim_url_end(slice: &[u8]) -> (&[u8], &[u8]) {
     let mut end = slice.len();
@@ -121,29 +202,16 @@ fn trim_url_end(slice: &[u8]) -> (&[u8], &[u8]) {
 // Core Logic
 // ============================================================================
 
-/// dispatch verbose to `decode_slice_to_writer` and `decode_in_place`
-macro_rules! decode {
-    ($func:ident($($args:expr),*), $verbose:expr) => {{
-        if $verbose {
-            #[cfg(feature = "verbose-log")]
-            {
-                let mut logger = VerboseLogger::new();
-                $func($($args),*, &mut logger)
-            }
-            #[cfg(not(feature = "verbose-log"))]
-            {
-                $func($($args),*)
-            }
+/// Run `$body` with `$recorder` reporting changes to `$logger`, or dropping
+/// them if it is disabled, so that the two are compiled separately.
+macro_rules! with_recorder {
+    ($logger:expr, $recorder:ident => $body:expr) => {{
+        if $logger.enabled() {
+            let $recorder = &mut ChangeRecorder::new($logger);
+            $body
         } else {
-            #[cfg(feature = "verbose-log")]
-            {
-                let mut logger = NoOpLogger;
-                $func($($args),*, &mut logger)
-            }
-            #[cfg(not(feature = "verbose-log"))]
-            {
-                $func($($args),*)
-            }
+            let $recorder = &mut NoOpRecorder;
+            $body
         }
     }};
 }
@@ -155,94 +223,144 @@ macro_rules! decode {
 pub fn decode_in_place(
     data: &mut [u8],
     escape_space: bool,
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
+    logger: &mut impl DecodeLogger,
 ) -> usize {
-    if escape_space {
-        decode_in_place_inner::<true>(
-            data,
-            #[cfg(feature = "verbose-log")]
-            logger,
-        )
+    decode_in_place_with(
+        data,
+        &DecodeOptions::with_escape_space(escape_space),
+        logger,
+    )
+    .0
+}
+
+/// Decode URL in-place with options.
+/// Returns the new length of the data, and whether it changed. Some options
+/// change the data without shrinking it.
+pub fn decode_in_place_with(
+    data: &mut [u8],
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
+) -> (usize, bool) {
+    let (len, urls) = decode_in_place_counted(data, options, logger);
+    (len, urls > 0)
+}
+
+/// Like [`decode_in_place_with`], but returns how many URLs changed instead of
+/// whether any did.
+fn decode_in_place_counted(
+    data: &mut [u8],
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
+) -> (usize, usize) {
+    with_recorder!(logger, recorder => if options.escape_space {
+        decode_in_place_inner::<true>(data, options, recorder)
     } else {
-        decode_in_place_inner::<false>(
-            data,
-            #[cfg(feature = "verbose-log")]
-            logger,
-        )
-    }
+        decode_in_place_inner::<false>(data, options, recorder)
+    })
 }
 
+/// Returns the new length of the data and how many URLs changed.
 #[inline(always)]
 fn decode_in_place_inner<const ESCAPE_SPACE: bool>(
     data: &mut [u8],
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
-) -> usize {
+    options: &DecodeOptions,
+    logger: &mut impl Recorder,
+) -> (usize, usize) {
     let mut r = 0;
     let mut w = 0;
+    let mut changed = 0;
     let len = data.len();
-    let finder = Finder::new(b"http");
+    let mut urls = UrlFinder::new(data, options);
+    let mut comments = options
+        .comments_only
+        .then(|| CommentTracker::new(options.comment_syntax.unwrap_or(CommentSyntax::NONE)));
+    let mut directives = DirectiveTracker::new(data, options.comment_syntax);
+    let mut transformer = Transformer::new(options);
+    let mut lines = LineTracker::new();
+    let mut quotes = QuoteTracker::new();
 
     while r < len {
-        if let Some(match_idx) = finder.find(&data[r..]) {
-            let start = r + match_idx;
-
-            let is_http = data[start..].starts_with(b"http://");
-            let is_https = data[start..].starts_with(b"https://");
-
-            if is_http || is_https {
-                // Copy plain text before URL
-                if start > r {
-                    let copy_len = start - r;
-                    if w != r {
-                        data.copy_within(r..start, w);
-                    }
-                    w += copy_len;
-                }
+        if let Some((start, prefix_len)) = urls.find(data, r) {
+            // Find URL end
+            let mut end = start + prefix_len;
+            while end < len && is_url_char(data[end]) {
+                end += 1;
+            }
+            if logger.enabled() {
+                let (line, column, column_utf16) = lines.locate(data, start, end);
+                logger.locate(start, line, column, column_utf16);
+            }
 
-                // Find URL end
-                let prefix_len = if is_https { 8 } else { 7 };
-                let mut end = start + prefix_len;
-                while end < len && is_url_char(data[end]) {
-                    end += 1;
+            // Trackers read the plain text, so ask them before any copy. All must
+            // see every URL.
+            let delim = quotes.delimiter(data, start, end);
+            let enabled = directives.url_enabled(data, start, end);
+            let in_scope = comments
+                .as_mut()
+                .is_none_or(|c| c.url_in_comment(data, start, end))
+                && enabled
+                && host_allowed(options, &data[start..end]);
+
+            // Copy plain text before URL
+            if start > r {
+                let copy_len = start - r;
+                if w != r {
+                    data.copy_within(r..start, w);
                 }
+                w += copy_len;
+            }
 
-                let mut valid_end = end;
-                while valid_end > start {
-                    if is_url_end_char(unsafe { *data.get_unchecked(valid_end - 1) }) {
-                        break;
-                    }
-                    valid_end -= 1;
+            if !in_scope {
+                if w != start {
+                    data.copy_within(start..end, w);
                 }
+                w += end - start;
+                r = end;
+                continue;
+            }
 
-                // Decode URL in-place
-                w = decode_url_in_place_indices::<ESCAPE_SPACE>(
-                    data,
-                    start,
-                    valid_end,
-                    w,
-                    #[cfg(feature = "verbose-log")]
-                    logger,
-                );
-
-                // Copy suffix after trimmed punctuation
-                let suffix_len = end - valid_end;
-                if suffix_len > 0 {
-                    if w != valid_end {
-                        data.copy_within(valid_end..end, w);
-                    }
-                    w += suffix_len;
+            let mut valid_end = end;
+            while valid_end > start {
+                if is_url_end_char(unsafe { *data.get_unchecked(valid_end - 1) }) {
+                    break;
                 }
+                valid_end -= 1;
+            }
 
-                r = end;
+            // Decode URL in-place
+            w = if let Some(t) = transformer.as_mut() {
+                let url_changed = t.transform(&data[start..valid_end], delim, start);
+                let out = t.output();
+                // only rewrite rules make a URL longer; keep it if it does not fit
+                if w + out.len() <= valid_end {
+                    log_url(logger, &data[start..valid_end], t, url_changed);
+                    changed += url_changed as usize;
+                    t.commit();
+                    data[w..w + out.len()].copy_from_slice(out);
+                    w + out.len()
+                } else {
+                    data.copy_within(start..valid_end, w);
+                    w + valid_end - start
+                }
             } else {
-                // Found `http` but not a url
-                let copy_len = start + 4 - r;
-                if w != r {
-                    data.copy_within(r..start + 4, w);
+                let url_end = decode_url_in_place_indices::<ESCAPE_SPACE>(
+                    data, start, valid_end, w, delim, logger,
+                );
+                // every decoded escape shrinks the URL
+                changed += (url_end - w < valid_end - start) as usize;
+                url_end
+            };
+
+            // Copy suffix after trimmed punctuation
+            let suffix_len = end - valid_end;
+            if suffix_len > 0 {
+                if w != valid_end {
+                    data.copy_within(valid_end..end, w);
                 }
-                w += copy_len;
-                r = start + 4;
+                w += suffix_len;
             }
+
+            r = end;
         } else {
             // Copy remaining
             if r < len {
@@ -255,7 +373,7 @@ fn decode_in_place_inner<const ESCAPE_SPACE: bool>(
             break;
         }
     }
-    w
+    (w, changed)
 }
 
 #[inline(always)]
@@ -264,10 +382,9 @@ fn decode_url_in_place_indices<const ESCAPE_SPACE: bool>(
     src_start: usize,
     src_end: usize,
     mut dst: usize,
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
+    delim: Option<u8>,
+    logger: &mut impl Recorder,
 ) -> usize {
-    #[cfg(not(feature = "verbose-log"))]
-    let mut logger = NoOpLogger;
     logger.clear();
 
     let mut i = src_start;
@@ -302,7 +419,7 @@ fn decode_url_in_place_indices<const ESCAPE_SPACE: bool>(
             let h1 = data[i + 1];
             let h2 = data[i + 2];
             if let Some(decoded) = decode_hex_pair(h1, h2) {
-                if ESCAPE_SPACE && decoded == b' ' {
+                if (ESCAPE_SPACE && decoded == b' ') || Some(decoded) == delim {
                     i += 3;
                     continue;
                 }
@@ -353,39 +470,43 @@ fn decode_url_in_place_indices<const ESCAPE_SPACE: bool>(
         dst += len;
     }
 
-    logger.print_if_changed(changed);
+    logger.finish(changed);
     dst
 }
 
+/// Log a URL decoded by [`Transformer`].
+#[inline(always)]
+fn log_url(logger: &mut impl Recorder, orig: &[u8], t: &Transformer, changed: bool) {
+    logger.clear();
+    logger.log_orig_slice(orig);
+    logger.log_res_slice(t.output());
+    logger.log_depth(t.depth());
+    logger.finish(changed);
+}
+
 #[cfg(not(feature = "safe"))]
 fn decode_file_in_place(
     path: &Path,
-    escape_space: bool,
-    #[allow(unused)] verbose: bool,
-    #[cfg(feature = "verbose-log")] p_counter: &AtomicUsize,
-    #[cfg(feature = "verbose-log")] c_counter: &AtomicUsize,
-) -> Result<()> {
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
+) -> Result<Outcome> {
     use std::fs::{self, OpenOptions};
 
     let metadata = fs::metadata(path).context(ReadInputSnafu)?;
     let file_len = metadata.len();
 
     if file_len == 0 {
-        #[cfg(feature = "verbose-log")]
-        p_counter.fetch_add(1, Ordering::Relaxed);
-        return Ok(());
+        return Ok(Outcome::Unchanged);
     }
 
-    #[allow(unused)]
-    let changed = if file_len < SMALL_FILE_THRESHOLD {
+    let (new_len, urls) = if file_len < SMALL_FILE_THRESHOLD {
         let mut buf = fs::read(path).context(ReadInputSnafu)?;
-        let new_len = decode!(decode_in_place(&mut buf, escape_space), verbose);
-        let is_changed = new_len < file_len as usize;
+        let (new_len, urls) = decode_in_place_counted(&mut buf, options, logger);
 
-        if is_changed {
+        if urls > 0 {
             fs::write(path, &buf[..new_len]).context(WriteOutputSnafu)?;
         }
-        is_changed
+        (new_len, urls)
     } else {
         let file = OpenOptions::new()
             .read(true)
@@ -400,31 +521,22 @@ fn decode_file_in_place(
         };
 
         #[cfg(unix)]
-        mmap.advise(memmap2::Advice::Sequential);
+        let _ = mmap.advise(memmap2::Advice::Sequential);
 
-        let new_len = decode!(decode_in_place(&mut mmap, escape_space), verbose);
-        let is_changed = new_len < file_len as usize;
+        let (new_len, urls) = decode_in_place_counted(&mut mmap, options, logger);
 
-        if is_changed {
+        if urls > 0 {
             mmap.flush().context(WriteOutputSnafu)?;
             drop(mmap);
             file.set_len(new_len as u64).context(WriteOutputSnafu)?;
         }
-        is_changed
+        (new_len, urls)
     };
 
-    #[cfg(feature = "verbose-log")]
-    {
-        p_counter.fetch_add(1, Ordering::Relaxed);
-        if changed {
-            c_counter.fetch_add(1, Ordering::Relaxed);
-            if verbose {
-                println!("Processed File: {:?}", path);
-            }
-        }
+    if urls > 0 {
+        logger.log_file(path);
     }
-
-    Ok(())
+    Ok(Outcome::new(urls, file_len, new_len as u64))
 }
 
 // region: to writer
@@ -433,56 +545,112 @@ pub fn decode_slice_to_writer<W: Write>(
     input: &[u8],
     writer: &mut W,
     escape_space: bool,
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
+    logger: &mut impl DecodeLogger,
 ) -> io::Result<bool> {
-    let mut pos = 0;
-    let len = input.len();
-    let mut changed = false;
-    let finder = Finder::new(b"http");
-
-    while pos < len {
-        if let Some(match_idx) = finder.find(&input[pos..]) {
-            let start = pos + match_idx;
-
-            let is_http = input[start..].starts_with(b"http://");
-            let is_https = input[start..].starts_with(b"https://");
+    decode_slice_to_writer_with(
+        input,
+        writer,
+        &DecodeOptions::with_escape_space(escape_space),
+        logger,
+    )
+}
 
-            if is_http || is_https {
-                // Write plain text before URL
-                if start > pos {
-                    writer.write_all(&input[pos..start])?;
-                }
+/// Decode URLs in `input` with options and write the result to `writer`.
+/// Returns whether anything changed.
+pub fn decode_slice_to_writer_with<W: Write>(
+    input: &[u8],
+    writer: &mut W,
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
+) -> io::Result<bool> {
+    decode_slice_to_writer_counted(input, writer, options, logger).map(|urls| urls > 0)
+}
 
-                // Find URL end
-                let prefix_len = if is_https { 8 } else { 7 };
-                let mut end = start + prefix_len;
-                while end < len && is_url_char(input[end]) {
-                    end += 1;
-                }
+/// Like [`decode_slice_to_writer_with`], but returns how many URLs changed
+/// instead of whether any did.
+fn decode_slice_to_writer_counted<W: Write>(
+    input: &[u8],
+    writer: &mut W,
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
+) -> io::Result<usize> {
+    with_recorder!(logger, recorder => if options.escape_space {
+        decode_slice_to_writer_inner::<true, W>(input, writer, options, recorder)
+    } else {
+        decode_slice_to_writer_inner::<false, W>(input, writer, options, recorder)
+    })
+}
 
-                let raw_url = &input[start..end];
-                let (valid_url, suffix) = trim_url_end(raw_url);
+/// Returns how many URLs changed.
+#[inline(always)]
+fn decode_slice_to_writer_inner<const ESCAPE_SPACE: bool, W: Write>(
+    input: &[u8],
+    writer: &mut W,
+    options: &DecodeOptions,
+    logger: &mut impl Recorder,
+) -> io::Result<usize> {
+    let mut pos = 0;
+    let len = input.len();
+    let mut changed = 0;
+    let mut urls = UrlFinder::new(input, options);
+    let mut comments = options
+        .comments_only
+        .then(|| CommentTracker::new(options.comment_syntax.unwrap_or(CommentSyntax::NONE)));
+    let mut directives = DirectiveTracker::new(input, options.comment_syntax);
+    let mut transformer = Transformer::new(options);
+    let mut lines = LineTracker::new();
+    let mut quotes = QuoteTracker::new();
 
-                // Decode URL and write directly
-                #[cfg(feature = "verbose-log")]
-                let url_changed = decode_url_to_writer(valid_url, writer, escape_space, logger)?;
-                #[cfg(not(feature = "verbose-log"))]
-                let url_changed = decode_url_to_writer(valid_url, writer, escape_space)?;
-                if url_changed {
-                    changed = true;
-                }
+    while pos < len {
+        if let Some((start, prefix_len)) = urls.find(input, pos) {
+            // Write plain text before URL
+            if start > pos {
+                writer.write_all(&input[pos..start])?;
+            }
 
-                // Write suffix after trimmed punctuation
-                if !suffix.is_empty() {
-                    writer.write_all(suffix)?;
-                }
+            // Find URL end
+            let mut end = start + prefix_len;
+            while end < len && is_url_char(input[end]) {
+                end += 1;
+            }
+            if logger.enabled() {
+                let (line, column, column_utf16) = lines.locate(input, start, end);
+                logger.locate(start, line, column, column_utf16);
+            }
 
+            let raw_url = &input[start..end];
+            let delim = quotes.delimiter(input, start, end);
+            let enabled = directives.url_enabled(input, start, end);
+            let in_scope = comments
+                .as_mut()
+                .is_none_or(|c| c.url_in_comment(input, start, end))
+                && enabled
+                && host_allowed(options, &input[start..end]);
+            if !in_scope {
+                writer.write_all(raw_url)?;
                 pos = end;
+                continue;
+            }
+            let (valid_url, suffix) = trim_url_end(raw_url);
+
+            // Decode URL and write directly
+            let url_changed = if let Some(t) = transformer.as_mut() {
+                let url_changed = t.transform(valid_url, delim, start);
+                log_url(logger, valid_url, t, url_changed);
+                writer.write_all(t.output())?;
+                t.commit();
+                url_changed
             } else {
-                // find `http` but not a url
-                writer.write_all(&input[pos..start + 4])?;
-                pos = start + 4;
+                decode_inner::<ESCAPE_SPACE, W>(valid_url, writer, delim, logger)?
+            };
+            changed += url_changed as usize;
+
+            // Write suffix after trimmed punctuation
+            if !suffix.is_empty() {
+                writer.write_all(suffix)?;
             }
+
+            pos = end;
         } else {
             // write all
             if pos < len {
@@ -499,34 +667,23 @@ pub fn decode_url_to_writer<W: Write>(
     url: &[u8],
     writer: &mut W,
     escape_space: bool,
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
+    logger: &mut impl DecodeLogger,
 ) -> io::Result<bool> {
     // static dispatch: completely remove `escape_space` branch at compile time
-    if escape_space {
-        decode_inner::<true, W>(
-            url,
-            writer,
-            #[cfg(feature = "verbose-log")]
-            logger,
-        )
+    with_recorder!(logger, recorder => if escape_space {
+        decode_inner::<true, W>(url, writer, None, recorder)
     } else {
-        decode_inner::<false, W>(
-            url,
-            writer,
-            #[cfg(feature = "verbose-log")]
-            logger,
-        )
-    }
+        decode_inner::<false, W>(url, writer, None, recorder)
+    })
 }
 
 #[inline(always)]
 fn decode_inner<const ESCAPE_SPACE: bool, W: Write>(
     url: &[u8],
     writer: &mut W,
-    #[cfg(feature = "verbose-log")] logger: &mut impl DecodeLogger,
+    delim: Option<u8>,
+    logger: &mut impl Recorder,
 ) -> io::Result<bool> {
-    #[cfg(not(feature = "verbose-log"))]
-    let mut logger = NoOpLogger;
     logger.clear();
 
     let first_pct = match memchr(b'%', url) {
@@ -555,7 +712,7 @@ fn decode_inner<const ESCAPE_SPACE: bool, W: Write>(
             let h1 = url[i + 1];
             let h2 = url[i + 2];
             if let Some(decoded) = decode_hex_pair(h1, h2) {
-                if ESCAPE_SPACE && decoded == b' ' {
+                if (ESCAPE_SPACE && decoded == b' ') || Some(decoded) == delim {
                     i += 3;
                     continue;
                 }
@@ -595,7 +752,7 @@ fn decode_inner<const ESCAPE_SPACE: bool, W: Write>(
         logger.log_res_slice(&url[literal_start..len]);
     }
 
-    logger.print_if_changed(changed);
+    logger.finish(changed);
     Ok(changed)
 }
 
@@ -603,17 +760,25 @@ fn decode_inner<const ESCAPE_SPACE: bool, W: Write>(
 pub fn decode_str(
     input: &str,
     escape_space: bool,
-    #[cfg(feature = "verbose-log")] verbose: bool,
+    logger: &mut impl DecodeLogger,
+) -> Result<(String, bool)> {
+    decode_str_with(
+        input,
+        &DecodeOptions::with_escape_space(escape_space),
+        logger,
+    )
+}
+
+/// Decode String with options.
+pub fn decode_str_with(
+    input: &str,
+    options: &DecodeOptions,
+    logger: &mut impl DecodeLogger,
 ) -> Result<(String, bool)> {
-    #[cfg(not(feature = "verbose-log"))]
-    let verbose = false;
     let mut buf = Vec::with_capacity(input.len());
 
-    let changed = decode!(
-        decode_slice_to_writer(input.as_bytes(), &mut buf, escape_space),
-        verbose
-    )
-    .context(WriteOutputSnafu)?;
+    let changed = decode_slice_to_writer_with(input.as_bytes(), &mut buf, options, logger)
+        .context(WriteOutputSnafu)?;
 
     Ok((
         simdutf8::basic::from_utf8(&buf)
@@ -623,32 +788,51 @@ pub fn decode_str(
     ))
 }
 
-/// Decode file and overwrite.
+/// Decode file and overwrite. Returns what changed.
 pub fn decode_file(
     path: impl AsRef<Path>,
     escape_space: bool,
     dry_run: bool,
-    #[cfg(feature = "verbose-log")] verbose: bool,
-    #[cfg(feature = "verbose-log")] p_counter: &AtomicUsize,
-    #[cfg(feature = "verbose-log")] c_counter: &AtomicUsize,
-) -> Result<()> {
-    #[cfg(not(feature = "verbose-log"))]
-    let verbose = false;
+    logger: &mut impl DecodeLogger,
+) -> Result<Outcome> {
+    decode_file_with(
+        path,
+        &DecodeOptions::with_escape_space(escape_space),
+        dry_run,
+        logger,
+    )
+}
 
+/// Decode file with options and overwrite. Returns what changed.
+///
+/// With `comments_only` and no `comment_syntax`, the syntax is detected by
+/// file extension, and files of unknown type are skipped.
+pub fn decode_file_with(
+    path: impl AsRef<Path>,
+    options: &DecodeOptions,
+    dry_run: bool,
+    logger: &mut impl DecodeLogger,
+) -> Result<Outcome> {
     let path = path.as_ref();
 
+    let resolved;
+    let options = if options.comments_only && options.comment_syntax.is_none() {
+        let Some(syntax) = CommentSyntax::from_path(path) else {
+            return Ok(Outcome::Skipped(SkipReason::UnknownCommentSyntax));
+        };
+        resolved = DecodeOptions {
+            comment_syntax: Some(syntax),
+            ..options.clone()
+        };
+        &resolved
+    } else {
+        options
+    };
+
     #[cfg(not(feature = "safe"))]
     {
-        if !dry_run {
-            return decode_file_in_place(
-                path,
-                escape_space,
-                verbose,
-                #[cfg(feature = "verbose-log")]
-                p_counter,
-                #[cfg(feature = "verbose-log")]
-                c_counter,
-            );
+        if !dry_run && !options.may_grow() && !options.access_log {
+            return decode_file_in_place(path, options, logger);
         }
     }
 
@@ -657,22 +841,20 @@ pub fn decode_file(
     let file_len = metadata.len();
 
     if file_len == 0 {
-        #[cfg(feature = "verbose-log")]
-        p_counter.fetch_add(1, Ordering::Relaxed);
-        return Ok(());
+        return Ok(Outcome::Unchanged);
     }
 
-    #[allow(unused)]
-    let mut changed = false;
-
-    #[allow(unused)]
-    if file_len < SMALL_FILE_THRESHOLD {
+    let (urls, new_len);
+    if options.access_log {
+        (urls, new_len) = decode_access_log_file(path, &metadata, dry_run, logger)?;
+    } else if file_len < SMALL_FILE_THRESHOLD && !options.may_grow() {
         let mut buf = fs::read(path).context(ReadInputSnafu)?;
-        let new_len = decode!(decode_in_place(&mut buf, escape_space), verbose);
-        changed = new_len < buf.len();
+        let len;
+        (len, urls) = decode_in_place_counted(&mut buf, options, logger);
+        new_len = len as u64;
 
-        if changed && !dry_run {
-            buf.truncate(new_len);
+        if urls > 0 && !dry_run {
+            buf.truncate(len);
             let parent = path.parent().unwrap_or_else(|| Path::new("."));
 
             let mut temp_file =
@@ -695,15 +877,13 @@ pub fn decode_file(
         };
 
         #[cfg(unix)]
-        mmap.advise(memmap2::Advice::Sequential);
+        let _ = mmap.advise(memmap2::Advice::Sequential);
 
         if dry_run {
-            let mut sink = io::sink();
-            changed = decode!(
-                decode_slice_to_writer(&mmap, &mut sink, escape_space),
-                verbose
-            )
-            .context(WriteOutputSnafu)?;
+            let mut sink = CountWriter::new(io::sink());
+            urls = decode_slice_to_writer_counted(&mmap, &mut sink, options, logger)
+                .context(WriteOutputSnafu)?;
+            new_len = sink.written;
         } else {
             let parent = path.parent().unwrap_or_else(|| Path::new("."));
 
@@ -711,19 +891,18 @@ pub fn decode_file(
                 NamedTempFile::new_in(parent).context(CreateTempSnafu { dir: parent })?;
 
             {
-                let mut buf_writer = BufWriter::with_capacity(IO_BUF_SIZE, &mut temp_file);
-                changed = decode!(
-                    decode_slice_to_writer(&mmap, &mut buf_writer, escape_space),
-                    verbose
-                )
-                .context(WriteOutputSnafu)?;
+                let mut buf_writer =
+                    CountWriter::new(BufWriter::with_capacity(IO_BUF_SIZE, &mut temp_file));
+                urls = decode_slice_to_writer_counted(&mmap, &mut buf_writer, options, logger)
+                    .context(WriteOutputSnafu)?;
                 buf_writer.flush().context(WriteOutputSnafu)?;
+                new_len = buf_writer.written;
             }
 
             drop(mmap);
             drop(file);
 
-            if changed {
+            if urls > 0 {
                 // Set permissions AFTER writing to avoid PermissionDenied if original is
                 // read-only
                 let _ = temp_file.as_file().set_permissions(metadata.permissions());
@@ -732,18 +911,74 @@ pub fn decode_file(
         }
     }
 
-    #[cfg(feature = "verbose-log")]
-    {
-        p_counter.fetch_add(1, Ordering::Relaxed);
-        if changed {
-            c_counter.fetch_add(1, Ordering::Relaxed);
-            if verbose {
-                println!("Processed File: {:?}", path);
-            }
-        }
+    if urls > 0 {
+        logger.log_file(path);
+    }
+    Ok(Outcome::new(urls, file_len, new_len))
+}
+
+/// Decode an access log through a temporary file, line by line. Returns how
+/// many fields changed and the new length.
+fn decode_access_log_file(
+    path: &Path,
+    metadata: &fs::Metadata,
+    dry_run: bool,
+    logger: &mut impl DecodeLogger,
+) -> Result<(usize, u64)> {
+    let file = fs::File::open(path).context(OpenInputSnafu { path })?;
+    let reader = io::BufReader::with_capacity(IO_BUF_SIZE, file);
+
+    if dry_run {
+        let mut sink = CountWriter::new(io::sink());
+        let urls = decode_access_log_counted(reader, &mut sink, logger).context(DecodeSnafu)?;
+        return Ok((urls, sink.written));
+    }
+
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(parent).context(CreateTempSnafu { dir: parent })?;
+    let (urls, new_len) = {
+        let mut buf_writer =
+            CountWriter::new(BufWriter::with_capacity(IO_BUF_SIZE, &mut temp_file));
+        let urls =
+            decode_access_log_counted(reader, &mut buf_writer, logger).context(DecodeSnafu)?;
+        buf_writer.flush().context(WriteOutputSnafu)?;
+        (urls, buf_writer.written)
+    };
+    if urls > 0 {
+        let _ = temp_file.as_file().set_permissions(metadata.permissions());
+        temp_file.persist(path).context(PersistTempSnafu { path })?;
+    }
+    Ok((urls, new_len))
+}
+
+/// Counts the bytes written through it.
+struct CountWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W> CountWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, written: 0 }
+    }
+}
+
+impl<W: Write> Write for CountWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
     }
 
-    Ok(())
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 #[cfg(test)]
@@ -752,6 +987,27 @@ mod tests {
     use tempfile::NamedTempFile;
 
     use super::*;
+    use crate::log::{NoOpLogger, VerboseLogger};
+
+    /// Decode `input` through a writer and in place, expecting `expected` from
+    /// both.
+    #[track_caller]
+    fn assert_decodes(input: &str, options: &DecodeOptions, expected: &str) {
+        let changed = input != expected;
+        assert_eq!(
+            decode_str_with(input, options, &mut NoOpLogger).unwrap(),
+            (expected.into(), changed)
+        );
+        let mut buf = input.as_bytes().to_vec();
+        let (new_len, in_place_changed) = decode_in_place_with(&mut buf, options, &mut NoOpLogger);
+        assert_eq!(
+            (
+                std::str::from_utf8(&buf[..new_len]).unwrap(),
+                in_place_changed
+            ),
+            (expected, changed)
+        );
+    }
 
     #[test]
     fn test_basic() {
@@ -760,8 +1016,7 @@ mod tests {
             decode_str(
                 "https://www.baidu.com/s?ie=UTF-8&wd=%E5%A4%A9%E6%B0%94",
                 false,
-                #[cfg(feature = "verbose-log")]
-                false
+                &mut NoOpLogger
             )
             .unwrap(),
             ("https://www.baidu.com/s?ie=UTF-8&wd=天气".into(), true)
@@ -771,8 +1026,7 @@ mod tests {
             decode_str(
                 "(https://www.baidu.com/s?ie=UTF-8&wd=%E5%A4%A9%E6%B0%94)",
                 false,
-                #[cfg(feature = "verbose-log")]
-                false
+                &mut NoOpLogger
             )
             .unwrap(),
             ("(https://www.baidu.com/s?ie=UTF-8&wd=天气)".into(), true)
@@ -782,8 +1036,7 @@ mod tests {
             decode_str(
                 "https://osu.ppy.sh/beatmapsets?q=malody%204k%20extra%20dan%20v3%E4%B8%AD",
                 true,
-                #[cfg(feature = "verbose-log")]
-                true
+                &mut VerboseLogger::new()
             )
             .unwrap(),
             (
@@ -793,17 +1046,449 @@ mod tests {
         );
         // nothing happens
         assert_eq!(
-            decode_str(
-                "https://osu.ppy.sh",
-                true,
-                #[cfg(feature = "verbose-log")]
-                false
-            )
-            .unwrap(),
+            decode_str("https://osu.ppy.sh", true, &mut NoOpLogger).unwrap(),
             ("https://osu.ppy.sh".into(), false)
         );
     }
 
+    #[test]
+    fn test_quote_delimiter() {
+        let cases = [
+            (
+                r#"s = "https://x.com/%22a%22%E4%B8%AD";"#,
+                r#"s = "https://x.com/%22a%22中";"#,
+            ),
+            (
+                "s = 'https://x.com/%27a%22';",
+                "s = 'https://x.com/%27a\"';",
+            ),
+            ("`https://x.com/%60%27`", "`https://x.com/%60'`"),
+            // text before the URL in the literal
+            (
+                r#"s = "see https://x.com/%22a%22 and https://x.com/%22";"#,
+                r#"s = "see https://x.com/%22a%22 and https://x.com/%22";"#,
+            ),
+            (
+                r#"f("a\"b", 'c https://x.com/%22%27') https://x.com/%22"#,
+                r#"f("a\"b", 'c https://x.com/"%27') https://x.com/""#,
+            ),
+            // not quoted: decode as usual
+            ("(https://x.com/%22)", "(https://x.com/\")"),
+        ];
+        for (input, expected) in cases {
+            assert_decodes(input, &DecodeOptions::default(), expected);
+        }
+    }
+
+    #[test]
+    fn test_comments_only() {
+        let input = r#"let a = "https://x.com/%E4%B8%AD"; // https://x.com/%E4%B8%AD
+/* see https://x.com/%E6%96%87 */ https://x.com/%E6%96%87"#;
+        let expected = r#"let a = "https://x.com/%E4%B8%AD"; // https://x.com/中
+/* see https://x.com/文 */ https://x.com/%E6%96%87"#;
+        let options = DecodeOptions {
+            comments_only: true,
+            comment_syntax: Some(CommentSyntax::C),
+            ..Default::default()
+        };
+        assert_decodes(input, &options, expected);
+
+        // comment markers inside string literals
+        let literal = r#"let files = glob("src/*.rs");
+let api = "https://x.com/%E4%B8%AD";"#;
+        assert_decodes(literal, &options, literal);
+        let literal = "url = \"https://x.com/#%E4%B8%AD\"  # https://x.com/%E4%B8%AD";
+        let hash = DecodeOptions {
+            comments_only: true,
+            comment_syntax: Some(CommentSyntax::HASH),
+            ..Default::default()
+        };
+        assert_decodes(
+            literal,
+            &hash,
+            "url = \"https://x.com/#%E4%B8%AD\"  # https://x.com/中",
+        );
+
+        // lifetimes are not quotes
+        let rust = DecodeOptions {
+            comment_syntax: Some(CommentSyntax::RUST),
+            ..options.clone()
+        };
+        assert_decodes(
+            "fn f<'a>(x: &'a str) {} // https://x.com/%E4%B8%AD",
+            &rust,
+            "fn f<'a>(x: &'a str) {} // https://x.com/中",
+        );
+
+        // syntax detected by extension
+        let dir = tempfile::tempdir().unwrap();
+        let (rs, txt) = (dir.path().join("a.rs"), dir.path().join("a.txt"));
+        fs::write(&rs, input).unwrap();
+        fs::write(&txt, input).unwrap();
+        let options = DecodeOptions {
+            comments_only: true,
+            ..Default::default()
+        };
+        assert!(
+            decode_file_with(&rs, &options, false, &mut NoOpLogger)
+                .unwrap()
+                .is_changed()
+        );
+        assert_eq!(
+            decode_file_with(&txt, &options, false, &mut NoOpLogger).unwrap(),
+            Outcome::Skipped(SkipReason::UnknownCommentSyntax)
+        );
+        assert_eq!(fs::read_to_string(rs).unwrap(), expected);
+        assert_eq!(fs::read_to_string(txt).unwrap(), input);
+    }
+
+    #[test]
+    fn test_directives() {
+        let input = "https://x.com/%E4%B8%AD
+<!-- urldecoder-disable-next-line -->
+https://x.com/%E4%B8%AD https://x.com/%E4%B8%AD
+https://x.com/%E4%B8%AD
+# urldecoder-disable
+https://x.com/%E4%B8%AD
+https://x.com/urldecoder-enable/%E4%B8%AD
+// urldecoder-enable
+https://x.com/%E4%B8%AD
+mentions urldecoder-disable-next-line
+https://x.com/%E4%B8%AD
+# urldecoder-disabled-foo
+https://x.com/%E4%B8%AD
+/* urldecoder-disable */
+// urldecoder-enablement
+https://x.com/%E4%B8%AD";
+        let expected = "https://x.com/中
+<!-- urldecoder-disable-next-line -->
+https://x.com/%E4%B8%AD https://x.com/%E4%B8%AD
+https://x.com/中
+# urldecoder-disable
+https://x.com/%E4%B8%AD
+https://x.com/urldecoder-enable/%E4%B8%AD
+// urldecoder-enable
+https://x.com/中
+mentions urldecoder-disable-next-line
+https://x.com/中
+# urldecoder-disabled-foo
+https://x.com/中
+/* urldecoder-disable */
+// urldecoder-enablement
+https://x.com/%E4%B8%AD";
+        assert_decodes(input, &DecodeOptions::default(), expected);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let input = "https://x.com/%25E4%25B8%25AD%2520?q=%252522 https://x.com/%25FF%41";
+        let cases = [
+            (1, "https://x.com/%E4%B8%AD%20?q=%2522 https://x.com/%FFA"),
+            (2, "https://x.com/中 ?q=%22 https://x.com/%FFA"),
+            (8, "https://x.com/中 ?q=\" https://x.com/%FFA"),
+        ];
+        for (max_depth, expected) in cases {
+            let options = DecodeOptions {
+                max_depth,
+                ..Default::default()
+            };
+            assert_decodes(input, &options, expected);
+        }
+    }
+
+    #[test]
+    fn test_nested_urls() {
+        let input = "https://a.com/r?url=https%3A%2F%2Fb.com%2F%25E4%25B8%25AD%3Fx%3D1%26y%3D%E6%96%87&q=%E6%96%87%26#%E6%96%87";
+        let options = DecodeOptions {
+            nested_urls: true,
+            ..Default::default()
+        };
+        let expected = "https://a.com/r?url=https://b.com/%25E4%25B8%25AD?x=1%26y=文&q=文&#文";
+        assert_decodes(input, &options, expected);
+
+        // the outer URL stays valid with further passes
+        let options = DecodeOptions {
+            max_depth: 4,
+            ..options
+        };
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap().0,
+            expected
+        );
+
+        assert_eq!(nested_urls(input), ["https://b.com/中?x=1&y=文"]);
+        assert_eq!(nested_urls("https://a.com/?q=1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_non_ascii_only() {
+        let input =
+            "https://x.com/%E4%B8%AD%2F%5B%7E%20/%E6%96%E6%96%87/%C0%AF%ED%A0%80%F0%9F%98%80%E4%B8";
+        let expected = "https://x.com/中%2F%5B%7E%20/%E6%96文/%C0%AF%ED%A0%80😀%E4%B8";
+        let options = DecodeOptions {
+            policy: DecodePolicy::NonAsciiOnly,
+            ..Default::default()
+        };
+        assert_decodes(input, &options, expected);
+    }
+
+    #[test]
+    fn test_uppercase_escapes() {
+        let input = r#""https://x.com/%e4%b8%ad%2f%5b%20%22%zz%2F" https://x.com/%2f%e4%b8"#;
+        let options = DecodeOptions {
+            escape_space: true,
+            uppercase_escapes: true,
+            policy: DecodePolicy::NonAsciiOnly,
+            ..Default::default()
+        };
+        let expected = r#""https://x.com/中%2F%5B%20%22%zz%2F" https://x.com/%2F%E4%B8"#;
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap(),
+            (expected.into(), true)
+        );
+
+        // same length, but changed
+        let input = "https://x.com/%2f";
+        let mut buf = input.as_bytes().to_vec();
+        let options = DecodeOptions {
+            policy: DecodePolicy::NonAsciiOnly,
+            ..options
+        };
+        let (new_len, changed) = decode_in_place_with(&mut buf, &options, &mut NoOpLogger);
+        assert_eq!(
+            (&buf[..new_len], changed),
+            (&b"https://x.com/%2F"[..], true)
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let input = "see HTTPS://x.com/a then https://Docs.RS:443/a/%2E%2E/b/./%E4%B8%AD?#top end";
+        let options = DecodeOptions {
+            normalize: true,
+            ..Default::default()
+        };
+        assert_decodes(
+            input,
+            &options,
+            "see HTTPS://x.com/a then https://docs.rs/b/中#top end",
+        );
+    }
+
+    #[test]
+    fn test_strip_params() {
+        let input = "a https://x.com/p?utm_source=tw&id=%E4%B8%AD&UTM_medium=x&fbclid=1#top b \
+                     https://x.com/?spm=1&from=2 c https://x.com/?ref=3 \
+                     d https://x.com/?utm_source=1&#a e https://x.com/?&utm_a=1 f https://x.com/?a=1&&b";
+        let options = DecodeOptions {
+            strip_params: DEFAULT_STRIP_PARAMS
+                .iter()
+                .map(|p| p.to_string())
+                .chain(["ref".into()])
+                .collect(),
+            ..Default::default()
+        };
+        let expected = "a https://x.com/p?id=中#top b https://x.com/ c https://x.com/ \
+                        d https://x.com/#a e https://x.com/ f https://x.com/?a=1&&b";
+        assert_decodes(input, &options, expected);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let rules = std::sync::Arc::new(
+            RewriteRules::parse("prefix https://old.com/ https://new.org/x/").unwrap(),
+        );
+        let hits = || rules.hits().map(|(_, n)| n).sum::<usize>();
+        let options = DecodeOptions {
+            rewrite: Some(rules.clone()),
+            ..Default::default()
+        };
+        let input = "https://old.com/a https://old.com/%E4%B8%AD";
+
+        // growing is fine through a writer
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap(),
+            ("https://new.org/x/a https://new.org/x/中".into(), true)
+        );
+        assert_eq!(hits(), 2);
+
+        // in place, a URL is kept if it does not fit
+        let mut buf = input.as_bytes().to_vec();
+        let (new_len, changed) = decode_in_place_with(&mut buf, &options, &mut NoOpLogger);
+        assert_eq!(
+            (std::str::from_utf8(&buf[..new_len]).unwrap(), changed),
+            ("https://old.com/a https://new.org/x/中", true)
+        );
+        // only the URL written counts
+        assert_eq!(hits(), 3);
+
+        let options = DecodeOptions {
+            url_hook: Some(UrlHook::new(|_| UrlAction::Keep)),
+            ..options
+        };
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap(),
+            (input.into(), false)
+        );
+        assert_eq!(hits(), 3);
+    }
+
+    #[test]
+    fn test_url_hook() {
+        let input = "a https://x.com/%E4%B8%AD b https://keep.com/%E4%B8%AD c https://x.com/old";
+        let options = DecodeOptions {
+            url_hook: Some(UrlHook::new(|ctx| {
+                if ctx.raw.starts_with(b"https://keep.com/") {
+                    UrlAction::Keep
+                } else if ctx.decoded.ends_with(b"/old") {
+                    UrlAction::Replace(format!("https://x.com/new#{}", ctx.offset).into())
+                } else {
+                    UrlAction::Decode
+                }
+            })),
+            ..Default::default()
+        };
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap(),
+            (
+                "a https://x.com/中 b https://keep.com/%E4%B8%AD c https://x.com/new#57".into(),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn test_hosts() {
+        let input = "https://a.com/%E4%B8%AD https://x.a.com/%E4%B8%AD https://b.com/%E4%B8%AD";
+        let options = DecodeOptions {
+            only_hosts: vec!["a.com".into(), "*.a.com".into(), "b.com".into()],
+            skip_hosts: vec!["B.com".into()],
+            ..Default::default()
+        };
+        let expected = "https://a.com/中 https://x.a.com/中 https://b.com/%E4%B8%AD";
+        assert_decodes(input, &options, expected);
+    }
+
+    #[test]
+    fn test_percent_u() {
+        let input =
+            "https://x.com/%u4E2D%u6587/%uD83D%uDE00/%u0041%u0020/%uD83D/%uDE00/%u12/%E4%B8%AD";
+        let options = DecodeOptions {
+            escape_space: true,
+            percent_u: true,
+            ..Default::default()
+        };
+        let expected = "https://x.com/中文/😀/A%u0020/%uD83D/%uDE00/%u12/中";
+        assert_eq!(
+            decode_str_with(input, &options, &mut NoOpLogger).unwrap(),
+            (expected.into(), true)
+        );
+    }
+
+    #[test]
+    fn test_changes() {
+        use crate::log::Change;
+
+        let input = "x https://a.com/%E4%B8%AD\n\nhttps://b.com/ \"https://c.com/%22%41\"";
+        let located = |changes: &[Change]| {
+            changes
+                .iter()
+                .map(|c| {
+                    (
+                        c.offset,
+                        c.line,
+                        c.column,
+                        c.original.clone(),
+                        c.decoded.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            (
+                2,
+                1,
+                3,
+                "https://a.com/%E4%B8%AD".to_owned(),
+                "https://a.com/中".to_owned(),
+            ),
+            (
+                43,
+                3,
+                17,
+                "https://c.com/%22%41".to_owned(),
+                "https://c.com/%22A".to_owned(),
+            ),
+        ];
+
+        let mut changes = Vec::new();
+        let mut buf = input.as_bytes().to_vec();
+        decode_in_place(&mut buf, false, &mut changes);
+        assert_eq!(located(&changes), expected);
+
+        let mut changes = Vec::new();
+        decode_slice_to_writer(input.as_bytes(), &mut Vec::new(), false, &mut changes).unwrap();
+        assert_eq!(located(&changes), expected);
+
+        // through the transformer
+        let options = DecodeOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let mut changes = Vec::new();
+        let mut buf = input.as_bytes().to_vec();
+        decode_in_place_with(&mut buf, &options, &mut changes);
+        assert_eq!(located(&changes), expected);
+    }
+
+    #[test]
+    fn test_file_urls() {
+        let input = "open file:///home/u/%E6%96%87%E6%A1%A3/a%2Fb%20c.pdf or https://a.com/a%2Fb";
+        let options = DecodeOptions {
+            file_urls: true,
+            ..Default::default()
+        };
+        let expected = "open file:///home/u/文档/a%2Fb c.pdf or https://a.com/a/b";
+        assert_decodes(input, &options, expected);
+
+        // off by default
+        let expected = "open file:///home/u/%E6%96%87%E6%A1%A3/a%2Fb%20c.pdf or https://a.com/a/b";
+        assert_eq!(
+            decode_str(input, false, &mut NoOpLogger).unwrap(),
+            (expected.into(), true)
+        );
+    }
+
+    #[test]
+    fn test_schemeless_urls() {
+        let input = r#"<img src="//cdn.example.com/%E5%9B%BE.png"> see www.example.com/%E6%96%87, a//b.com/%E4%B8%AD"#;
+        let options = DecodeOptions {
+            detect_scheme_relative: true,
+            detect_www: true,
+            ..Default::default()
+        };
+        let expected =
+            r#"<img src="//cdn.example.com/图.png"> see www.example.com/文, a//b.com/%E4%B8%AD"#;
+        assert_decodes(input, &options, expected);
+
+        // off by default
+        assert_eq!(
+            decode_str(input, false, &mut NoOpLogger).unwrap(),
+            (input.into(), false)
+        );
+    }
+
+    #[test]
+    fn test_scheme_boundary() {
+        let input = "xhttp://a.com/%E4%B8%AD git+https://a.com/%E4%B8%AD (https://a.com/%E4%B8%AD)";
+        let options = DecodeOptions {
+            scheme_boundary: SchemeBoundary::Word,
+            ..Default::default()
+        };
+        let expected = "xhttp://a.com/%E4%B8%AD git+https://a.com/%E4%B8%AD (https://a.com/中)";
+        assert_decodes(input, &options, expected);
+    }
+
     #[test]
     fn test_long_url() {
         let mut url = "https://www.baidu.com/s?ie=UTF-8&wd=%E5%A4%A9%E6%B0%94".to_string();
@@ -811,13 +1496,7 @@ mod tests {
             url.push_str("%20");
         }
         assert_eq!(
-            decode_str(
-                &url,
-                false,
-                #[cfg(feature = "verbose-log")]
-                false
-            )
-            .unwrap(),
+            decode_str(&url, false, &mut NoOpLogger).unwrap(),
             (
                 "https://www.baidu.com/s?ie=UTF-8&wd=天气".to_string() + " ".repeat(10000).as_str(),
                 true
@@ -826,13 +1505,7 @@ mod tests {
 
         let base = "a".repeat(60000);
         assert_eq!(
-            decode_str(
-                &(base.clone() + &url),
-                false,
-                #[cfg(feature = "verbose-log")]
-                false
-            )
-            .unwrap(),
+            decode_str(&(base.clone() + &url), false, &mut NoOpLogger).unwrap(),
             (
                 (base + "https://www.baidu.com/s?ie=UTF-8&wd=天气") + " ".repeat(10000).as_str(),
                 true
@@ -847,22 +1520,27 @@ mod tests {
         let test_str = "xxxxhttps://www.baidu.com/s?ie=UTF-8&wd=%E5%A4%A9%E6%B0%94xxxx";
         fs::write(&t1, test_str).unwrap();
 
-        decode_file(
-            &t1,
-            false,
-            false,
-            #[cfg(feature = "verbose-log")]
-            false,
-            #[cfg(feature = "verbose-log")]
-            &AtomicUsize::new(0),
-            #[cfg(feature = "verbose-log")]
-            &AtomicUsize::new(0),
-        )
-        .unwrap();
+        let changed = Outcome::Changed {
+            urls: 1,
+            bytes_saved: 12,
+        };
+        assert_eq!(
+            decode_file(&t1, false, true, &mut NoOpLogger).unwrap(),
+            changed
+        );
+        assert_eq!(fs::read_to_string(&t1).unwrap(), test_str);
+        assert_eq!(
+            decode_file(&t1, false, false, &mut NoOpLogger).unwrap(),
+            changed
+        );
 
         assert_eq!(
-            fs::read_to_string(t1).unwrap(),
+            fs::read_to_string(&t1).unwrap(),
             "xxxxhttps://www.baidu.com/s?ie=UTF-8&wd=天气xxxx"
         );
+        assert_eq!(
+            decode_file(&t1, false, false, &mut NoOpLogger).unwrap(),
+            Outcome::Unchanged
+        );
     }
 }
@@ -1,24 +1,147 @@
-use std::io::{self, Write as _};
+use std::{
+    borrow::Cow,
+    io::{self, Write as _},
+    path::Path,
+};
 
+use memchr::{memchr_iter, memrchr};
+
+/// A URL changed by decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Change {
+    /// Byte offset of the URL in the input.
+    pub offset: usize,
+    /// 1-based line of the URL.
+    pub line: usize,
+    /// 1-based column of the URL, in bytes.
+    pub column: usize,
+    /// 1-based column of the URL, in UTF-16 code units as editors and SARIF
+    /// count them.
+    pub column_utf16: usize,
+    /// The URL as written.
+    pub original: String,
+    /// The URL as written back.
+    pub decoded: String,
+    /// How many decoding passes changed the URL, see
+    /// [`DecodeOptions::max_depth`](crate::DecodeOptions::max_depth).
+    pub depth: usize,
+    /// Whether the bytes of `original` or `decoded` are not valid UTF-8, so
+    /// that the strings hold U+FFFD in place of the bytes written.
+    pub lossy: bool,
+}
+
+/// Receives each URL changed by decoding.
+///
+/// Collect them into a `Vec<Change>`:
+///
+/// ```
+/// use urldecoder::{decode_in_place, log::Change};
+///
+/// let mut data = b"a\n see https://x.com/%E4%B8%AD".to_vec();
+/// let mut changes: Vec<Change> = Vec::new();
+/// decode_in_place(&mut data, false, &mut changes);
+/// assert_eq!((changes[0].line, changes[0].column), (2, 6));
+/// assert_eq!(changes[0].decoded, "https://x.com/中");
+/// ```
 pub trait DecodeLogger {
-    fn new() -> Self
-    where
-        Self: Sized;
+    /// Whether to report changes at all. If not, decoding does not collect
+    /// them.
+    fn enabled(&self) -> bool {
+        true
+    }
+    fn log_change(&mut self, change: Change);
+    /// Called after decoding changed a file.
+    fn log_file(&mut self, _path: &Path) {}
+}
+
+impl DecodeLogger for Vec<Change> {
+    fn log_change(&mut self, change: Change) {
+        self.push(change);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NoOpLogger;
+impl DecodeLogger for NoOpLogger {
+    #[inline(always)]
+    fn enabled(&self) -> bool {
+        false
+    }
+    #[inline(always)]
+    fn log_change(&mut self, _: Change) {}
+}
+
+const LOG_RES_CAPACITY: usize = 256;
+const LOG_ORIG_CAPACITY: usize = LOG_RES_CAPACITY * 3;
+
+const ELLIPSIS: &[u8; 3] = b"...";
+
+/// Prints each change to stdout as a colored diff.
+#[derive(Default)]
+pub struct VerboseLogger;
+
+impl VerboseLogger {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl DecodeLogger for VerboseLogger {
+    fn log_change(&mut self, change: Change) {
+        let stdout = io::stdout();
+        let handle = stdout.lock();
+        let mut writer = io::BufWriter::new(handle);
+        writer.write_all("\x1b[31m- ".as_bytes()).unwrap();
+        write_truncated(&mut writer, change.original.as_bytes(), LOG_ORIG_CAPACITY);
+        writer.write_all("\x1b[0m\n\x1b[32m+ ".as_bytes()).unwrap();
+        write_truncated(&mut writer, change.decoded.as_bytes(), LOG_RES_CAPACITY);
+        if change.depth > 1 {
+            write!(writer, " (encoding depth: {})", change.depth).unwrap();
+        }
+        writer.write_all("\x1b[0m\n".as_bytes()).unwrap();
+        writer.flush().unwrap();
+    }
+    fn log_file(&mut self, path: &Path) {
+        println!("Processed File: {:?}", path);
+    }
+}
+
+fn write_truncated(writer: &mut impl io::Write, text: &[u8], capacity: usize) {
+    if text.len() > capacity {
+        writer.write_all(&text[..capacity]).unwrap();
+        writer.write_all(ELLIPSIS).unwrap();
+    } else {
+        writer.write_all(text).unwrap();
+    }
+}
+
+/// Collects the bytes of a URL while decoding it, for a [`DecodeLogger`].
+pub(crate) trait Recorder {
+    /// Whether anything is recorded, so that callers can skip the work of
+    /// feeding a recorder that drops it.
+    fn enabled(&self) -> bool;
+    /// Set where the next URL is.
+    fn locate(&mut self, offset: usize, line: usize, column: usize, column_utf16: usize);
     fn log_orig(&mut self, byte: u8);
     fn log_orig_slice(&mut self, slice: &[u8]);
     fn log_res(&mut self, byte: u8);
     fn log_res_slice(&mut self, slice: &[u8]);
-    fn print_if_changed(&mut self, changed: bool);
+    fn log_depth(&mut self, _depth: usize) {}
+    /// End the URL, reporting it if it changed.
+    fn finish(&mut self, changed: bool);
     fn clear(&mut self);
 }
 
-pub struct NoOpLogger;
-impl DecodeLogger for NoOpLogger {
+pub(crate) struct NoOpRecorder;
+impl Recorder for NoOpRecorder {
     #[inline(always)]
-    fn new() -> Self {
-        Self
+    fn enabled(&self) -> bool {
+        false
     }
     #[inline(always)]
+    fn locate(&mut self, _: usize, _: usize, _: usize, _: usize) {}
+    #[inline(always)]
     fn log_orig(&mut self, _: u8) {}
     #[inline(always)]
     fn log_orig_slice(&mut self, _: &[u8]) {}
@@ -27,125 +150,158 @@ impl DecodeLogger for NoOpLogger {
     #[inline(always)]
     fn log_res_slice(&mut self, _: &[u8]) {}
     #[inline(always)]
-    fn print_if_changed(&mut self, _: bool) {}
+    fn finish(&mut self, _: bool) {}
     #[inline(always)]
     fn clear(&mut self) {}
 }
 
-const LOG_RES_CAPACITY: usize = 256;
-const LOG_ORIG_CAPACITY: usize = LOG_RES_CAPACITY * 3;
-
-const ELLIPSIS: &[u8; 3] = b"...";
-
-pub struct VerboseLogger {
-    res_len: usize,
-    res_buf: [u8; LOG_RES_CAPACITY],
-    orig_len: usize,
-    orig_buf: [u8; LOG_ORIG_CAPACITY],
+/// Records changes for a [`DecodeLogger`].
+pub(crate) struct ChangeRecorder<'a, L: DecodeLogger> {
+    logger: &'a mut L,
+    offset: usize,
+    line: usize,
+    column: usize,
+    column_utf16: usize,
+    depth: usize,
+    orig: Vec<u8>,
+    res: Vec<u8>,
 }
 
-impl DecodeLogger for VerboseLogger {
-    #[inline]
-    fn new() -> Self {
+impl<'a, L: DecodeLogger> ChangeRecorder<'a, L> {
+    pub(crate) fn new(logger: &'a mut L) -> Self {
         Self {
-            res_len: 0,
-            res_buf: [0; LOG_RES_CAPACITY],
-            orig_len: 0,
-            orig_buf: [0; LOG_ORIG_CAPACITY],
+            logger,
+            offset: 0,
+            line: 1,
+            column: 1,
+            column_utf16: 1,
+            depth: 0,
+            orig: Vec::new(),
+            res: Vec::new(),
         }
     }
+}
 
+impl<L: DecodeLogger> Recorder for ChangeRecorder<'_, L> {
     #[inline(always)]
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn locate(&mut self, offset: usize, line: usize, column: usize, column_utf16: usize) {
+        self.offset = offset;
+        self.line = line;
+        self.column = column;
+        self.column_utf16 = column_utf16;
+    }
+
+    #[inline]
     fn log_orig(&mut self, byte: u8) {
-        if self.orig_len < LOG_ORIG_CAPACITY {
-            unsafe {
-                *self.orig_buf.get_unchecked_mut(self.orig_len) = byte;
-            }
-            self.orig_len += 1;
-        }
+        self.orig.push(byte);
     }
 
-    #[inline(always)]
+    #[inline]
     fn log_orig_slice(&mut self, slice: &[u8]) {
-        unsafe {
-            if self.orig_len + slice.len() < LOG_ORIG_CAPACITY {
-                self.orig_buf
-                    .get_unchecked_mut(self.orig_len..self.orig_len + slice.len())
-                    .copy_from_slice(slice);
-                self.orig_len += slice.len();
-            } else {
-                let cp = LOG_ORIG_CAPACITY - self.orig_len;
-                self.orig_buf
-                    .get_unchecked_mut(self.orig_len..LOG_ORIG_CAPACITY)
-                    .copy_from_slice(&slice[..cp]);
-                self.orig_len = LOG_ORIG_CAPACITY;
-            }
-        }
+        self.orig.extend_from_slice(slice);
     }
 
-    #[inline(always)]
+    #[inline]
     fn log_res(&mut self, byte: u8) {
-        if self.res_len < LOG_RES_CAPACITY {
-            unsafe {
-                *self.res_buf.get_unchecked_mut(self.res_len) = byte;
-            }
-            self.res_len += 1;
-        }
+        self.res.push(byte);
     }
 
-    #[inline(always)]
+    #[inline]
     fn log_res_slice(&mut self, slice: &[u8]) {
-        unsafe {
-            if self.res_len + slice.len() < LOG_RES_CAPACITY {
-                self.res_buf
-                    .get_unchecked_mut(self.res_len..self.res_len + slice.len())
-                    .copy_from_slice(slice);
-                self.res_len += slice.len();
-            } else {
-                let cp = LOG_RES_CAPACITY - self.res_len;
-                self.res_buf
-                    .get_unchecked_mut(self.res_len..LOG_RES_CAPACITY)
-                    .copy_from_slice(&slice[..cp]);
-                self.res_len = LOG_RES_CAPACITY;
-            }
-        }
+        self.res.extend_from_slice(slice);
     }
 
     #[inline]
-    fn print_if_changed(&mut self, changed: bool) {
+    fn log_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    fn finish(&mut self, changed: bool) {
         if !changed {
             return;
         }
-
-        self.print_impl();
+        let original = String::from_utf8_lossy(&self.orig);
+        let decoded = String::from_utf8_lossy(&self.res);
+        let lossy = matches!(original, Cow::Owned(_)) || matches!(decoded, Cow::Owned(_));
+        self.logger.log_change(Change {
+            offset: self.offset,
+            line: self.line,
+            column: self.column,
+            column_utf16: self.column_utf16,
+            original: original.into_owned(),
+            decoded: decoded.into_owned(),
+            depth: self.depth.max(1),
+            lossy,
+        });
     }
 
-    #[inline(always)]
+    #[inline]
     fn clear(&mut self) {
-        self.res_len = 0;
-        self.orig_len = 0;
+        self.depth = 0;
+        self.orig.clear();
+        self.res.clear();
     }
 }
 
-impl VerboseLogger {
-    fn print_impl(&mut self) {
-        let orig = &self.orig_buf[..self.orig_len];
-        let res = &self.res_buf[..self.res_len];
+/// Tracks the line and column of URLs found in increasing order.
+pub(crate) struct LineTracker {
+    line: usize,
+    line_start: usize,
+    scanned: usize,
+    /// UTF-16 code units from `line_start` to `scanned`.
+    units: usize,
+}
 
-        let stdout = io::stdout();
-        let handle = stdout.lock();
-        let mut writer = io::BufWriter::new(handle);
-        writer.write_all("\x1b[31m- ".as_bytes()).unwrap();
-        writer.write_all(orig).unwrap();
-        if self.orig_len == LOG_ORIG_CAPACITY {
-            writer.write_all(ELLIPSIS).unwrap();
+impl LineTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            line: 1,
+            line_start: 0,
+            scanned: 0,
+            units: 0,
         }
-        writer.write_all("\x1b[0m\n\x1b[32m+ ".as_bytes()).unwrap();
-        writer.write_all(res).unwrap();
-        if self.res_len == LOG_RES_CAPACITY {
-            writer.write_all(ELLIPSIS).unwrap();
+    }
+
+    /// Line, byte column and UTF-16 column of the URL at `start..end` of
+    /// `data`. The bytes from the end of the previous URL on must still be the
+    /// input ones; a URL has no line break.
+    #[inline]
+    pub(crate) fn locate(
+        &mut self,
+        data: &[u8],
+        start: usize,
+        end: usize,
+    ) -> (usize, usize, usize) {
+        let text = &data[self.scanned..start];
+        match memrchr(b'\n', text) {
+            Some(last) => {
+                self.line += memchr_iter(b'\n', text).count();
+                self.line_start = self.scanned + last + 1;
+                self.units = utf16_len(&data[self.line_start..start]);
+            }
+            None => self.units += utf16_len(text),
         }
-        writer.write_all("\x1b[0m\n".as_bytes()).unwrap();
-        writer.flush().unwrap();
+        let column_utf16 = self.units + 1;
+        self.units += utf16_len(&data[start..end]);
+        self.scanned = end;
+        (self.line, start - self.line_start + 1, column_utf16)
     }
 }
+
+/// UTF-16 code units of the UTF-8 `bytes`.
+#[inline]
+pub(crate) fn utf16_len(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..0xC0 => 0,
+            0xF0.. => 2,
+            _ => 1,
+        })
+        .sum()
+}
@@ -1,21 +1,38 @@
 #![cfg(feature = "bin")]
-use std::path::{Path, PathBuf};
-#[cfg(feature = "verbose-log")]
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    io,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
-use clap::{ArgAction, Parser};
-use glob::glob;
-use rayon::prelude::*;
+use clap::{ArgAction, Args, Parser, Subcommand};
 use snafu::ResultExt;
-use urldecoder::decode_file;
+use urldecoder::{
+    DEFAULT_STRIP_PARAMS, DecodeOptions, DecodePolicy, MAX_DEPTH, RewriteRules, SchemeBoundary,
+    batch::{BatchDecoder, Summary, expand_globs},
+    log::VerboseLogger,
+    rename::{Rename, RenamePlan},
+    report::{Format, write_report},
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None, after_help = r#"Examples:
 urldecoder test/t.md        # decode test/t.md
 urldecoder *.md -e my.md    # decode all markdown files in current folder except `my.md`
 urldecoder **/*             # decode all files recursively in current folder
-"#)]
+urldecoder rename assets    # decode file and folder names under `assets`
+
+Exit status: 0 if nothing changed, 1 on errors, 3 if files changed, 4 if files
+would change in a dry run.
+"#, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Files to process, allows wildcard pattern
     #[clap(required = true)]
     files: Vec<String>,
@@ -28,6 +45,16 @@ struct Cli {
     #[arg(short, long)]
     no_output: bool,
 
+    /// Stop at the first file that fails to decode
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Output format: human-readable text, a JSON report (json), one JSON
+    /// object per line (jsonl), or a SARIF log of the URLs to decode (sarif,
+    /// implies --dry-run)
+    #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = ["text", "json", "jsonl", "sarif"])]
+    format: String,
+
     /// Exclude file or folder by relative path prefix
     #[arg(short, long, action = ArgAction::Append)]
     exclude: Vec<PathBuf>,
@@ -35,87 +62,316 @@ struct Cli {
     /// Do not decode `%20` to space
     #[arg(long)]
     escape_space: bool,
+
+    /// Only decode URLs in comments, detecting comment syntax by file
+    /// extension; files of unknown type are skipped
+    #[arg(long)]
+    comments_only: bool,
+
+    /// Decode double-encoded URLs (such as `%2520`) repeatedly while they
+    /// change, at most N times in total
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=MAX_DEPTH as i64))]
+    max_depth: u8,
+
+    /// Keep URLs nested in query parameters (redirect links) encoded enough
+    /// for the outer URL to stay valid
+    #[arg(long)]
+    nested_urls: bool,
+
+    /// Only decode escapes forming non-ASCII characters, keep ASCII escapes
+    /// such as `%2F` as written
+    #[arg(long)]
+    non_ascii_only: bool,
+
+    /// Uppercase the hex digits of escapes kept encoded, such as `%2f` to `%2F`
+    #[arg(long)]
+    uppercase_escapes: bool,
+
+    /// Normalize URLs: lowercase scheme and host, remove default ports, resolve
+    /// dot segments and remove an empty query
+    #[arg(long)]
+    normalize: bool,
+
+    /// Remove tracking query parameters (utm_*, fbclid, gclid, spm, from), plus
+    /// the given comma-separated names or `prefix*` patterns
+    #[arg(long, value_name = "PATTERNS", num_args = 0..=1, require_equals = true, value_delimiter = ',')]
+    strip_params: Option<Vec<String>>,
+
+    /// Rewrite URLs by the prefix or regex rules of a file, one
+    /// `prefix|regex <from> <to>` per line; prints how often each rule fired
+    #[arg(long, value_name = "FILE")]
+    rewrite_rules: Option<PathBuf>,
+
+    /// Only decode URLs of this host; `*.example.com` matches subdomains
+    #[arg(long, value_name = "HOST", action = ArgAction::Append)]
+    only_host: Vec<String>,
+
+    /// Do not decode URLs of this host; `*.example.com` matches subdomains
+    #[arg(long, value_name = "HOST", action = ArgAction::Append)]
+    skip_host: Vec<String>,
+
+    /// Also decode non-standard `%uXXXX` escapes (IIS, JavaScript `escape()`)
+    #[arg(long)]
+    percent_u: bool,
+
+    /// Also detect scheme-relative URLs such as `//cdn.example.com/a.png`
+    #[arg(long)]
+    scheme_relative: bool,
+
+    /// Also detect bare domain URLs such as `www.example.com/a`
+    #[arg(long)]
+    www: bool,
+
+    /// Also detect `file://` URLs, keeping `%2F` inside path segments encoded
+    #[arg(long)]
+    file_urls: bool,
+
+    /// What may precede `http://` for it to start a URL: anything (any), no
+    /// scheme character (word), or whitespace and opening punctuation (strict)
+    #[arg(long, value_name = "RULE", default_value = "any", value_parser = ["any", "word", "strict"])]
+    scheme_boundary: String,
+
+    /// Treat files as access logs (Common or Combined Log Format), decoding
+    /// request paths and Referer fields
+    #[arg(long)]
+    access_log: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode percent-encoded file and folder names
+    Rename(RenameArgs),
+}
+
+#[derive(Args)]
+struct RenameArgs {
+    /// Files to rename, or folders whose contents to rename recursively
+    #[clap(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Show renames only, without renaming
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// Also update relative links in Markdown and HTML files that point at
+    /// renamed files
+    #[arg(long)]
+    update_links: bool,
 }
 
-#[inline]
-fn in_exclude(exclude: &[PathBuf], path: &Path) -> bool {
-    exclude.iter().any(|p| path.starts_with(p) || path == p)
+/// Exit status on errors. clap exits with 2 on usage errors.
+const EXIT_ERROR: u8 = 1;
+/// Exit status when files changed.
+const EXIT_CHANGED: u8 = 3;
+/// Exit status when files would change in a dry run.
+const EXIT_WOULD_CHANGE: u8 = 4;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", snafu::Report::from_error(e));
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
 }
 
-fn main() -> Result<(), snafu::Whatever> {
+fn run() -> Result<ExitCode, snafu::Whatever> {
     let mut cli = Cli::parse();
+    if let Some(Command::Rename(args)) = cli.command.take() {
+        return rename(&args).map(|()| ExitCode::SUCCESS);
+    }
 
     cli.exclude.push("node_modules".into());
 
-    process_directory(
+    let rewrite = cli
+        .rewrite_rules
+        .as_ref()
+        .map(RewriteRules::from_file)
+        .transpose()
+        .whatever_context("Failed to load rewrite rules")?
+        .map(Arc::new);
+
+    let options = DecodeOptions {
+        escape_space: cli.escape_space,
+        comments_only: cli.comments_only,
+        max_depth: cli.max_depth.into(),
+        nested_urls: cli.nested_urls,
+        policy: if cli.non_ascii_only {
+            DecodePolicy::NonAsciiOnly
+        } else {
+            DecodePolicy::All
+        },
+        uppercase_escapes: cli.uppercase_escapes,
+        normalize: cli.normalize,
+        strip_params: cli
+            .strip_params
+            .map(|extra| {
+                DEFAULT_STRIP_PARAMS
+                    .iter()
+                    .map(|p| p.to_string())
+                    .chain(extra)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        rewrite,
+        only_hosts: cli.only_host,
+        skip_hosts: cli.skip_host,
+        percent_u: cli.percent_u,
+        detect_scheme_relative: cli.scheme_relative,
+        detect_www: cli.www,
+        file_urls: cli.file_urls,
+        scheme_boundary: match cli.scheme_boundary.as_str() {
+            "word" => SchemeBoundary::Word,
+            "strict" => SchemeBoundary::Strict,
+            _ => SchemeBoundary::Any,
+        },
+        access_log: cli.access_log,
+        ..Default::default()
+    };
+
+    let format = match cli.format.as_str() {
+        "json" => Some(Format::Json),
+        "jsonl" => Some(Format::JsonLines),
+        "sarif" => Some(Format::Sarif),
+        _ => None,
+    };
+    let dry_run = cli.dry_run || format == Some(Format::Sarif);
+    let code = process_directory(
         cli.files,
         &cli.exclude,
-        cli.escape_space,
-        cli.dry_run,
-        #[cfg(feature = "verbose-log")]
+        &options,
+        dry_run,
         !cli.no_output,
+        cli.fail_fast,
+        format,
     )?;
 
+    if let (Some(rules), None) = (&options.rewrite, format) {
+        println!(
+            "Rewrite rules {}:",
+            if dry_run { "that would fire" } else { "fired" }
+        );
+        for (rule, hits) in rules.hits() {
+            println!("{hits:>8}  {rule}");
+        }
+    }
+
+    Ok(code)
+}
+
+fn rename(args: &RenameArgs) -> Result<(), snafu::Whatever> {
+    let plan = RenamePlan::new(&args.paths).whatever_context("Failed to scan files")?;
+    for Rename { from, to } in &plan.collisions {
+        eprintln!(
+            "SKIP {} -> {}: name already taken",
+            from.display(),
+            to.display()
+        );
+    }
+    if args.update_links {
+        for update in plan
+            .update_links(args.dry_run)
+            .whatever_context("Failed to update links")?
+        {
+            println!("{}: {} links", update.path.display(), update.links);
+        }
+    }
+    for Rename { from, to } in &plan.renames {
+        println!("{} -> {}", from.display(), to.display());
+    }
+    if !args.dry_run {
+        plan.apply().whatever_context("Failed to rename")?;
+    }
+    println!(
+        "{} {} files and folders, {} skipped.",
+        if args.dry_run {
+            "Would rename"
+        } else {
+            "Renamed"
+        },
+        plan.renames.len(),
+        plan.collisions.len()
+    );
     Ok(())
 }
 
 fn process_directory(
     files: Vec<String>,
     exclude: &[PathBuf],
-    escape_space: bool,
+    options: &DecodeOptions,
     dry_run: bool,
-    #[cfg(feature = "verbose-log")] verbose: bool,
-) -> Result<(), snafu::Whatever> {
-    let mut paths = Vec::new();
-    for pattern in &files {
-        for path in
-            (glob(pattern).with_whatever_context(|e| format!("Glob pattern error: {e}"))?).flatten()
-        {
-            if path.is_file() && !in_exclude(exclude, &path) {
-                paths.push(path);
-            }
-        }
-    }
+    verbose: bool,
+    fail_fast: bool,
+    format: Option<Format>,
+) -> Result<ExitCode, snafu::Whatever> {
+    let paths = expand_globs(&files, exclude).whatever_context("Failed to expand globs")?;
 
-    if paths.is_empty() {
+    if paths.is_empty() && format.is_none() {
         println!("No files found.");
-        return Ok(());
+        return Ok(ExitCode::SUCCESS);
     }
-    #[cfg(feature = "verbose-log")]
-    {
-        let processed_count = AtomicUsize::new(0);
-        let changed_count = AtomicUsize::new(0);
-
-        paths.par_iter().for_each(|path| {
-            if let Err(e) = decode_file(
-                path,
-                escape_space,
-                dry_run,
-                verbose,
-                &processed_count,
-                &changed_count,
-            ) {
-                eprintln!("ERROR processing {}: {}", path.display(), e);
+
+    let failed = AtomicBool::new(false);
+    let mut decoder = BatchDecoder::new(options).dry_run(dry_run);
+    if fail_fast {
+        decoder = decoder.cancel_flag(&failed).on_progress(|p| {
+            if p.result.is_err() {
+                failed.store(true, Ordering::Relaxed);
             }
         });
-
-        println!(
-            "Processed {} files, {} files changed.",
-            processed_count.load(Ordering::Relaxed),
-            changed_count.load(Ordering::Relaxed)
-        );
     }
+    Ok(match format {
+        Some(format) => {
+            let summary = decoder.decode_paths_with(&paths, |_| Vec::new());
+            write_report(&mut io::stdout().lock(), &summary, format)
+                .whatever_context("Failed to write report")?;
+            exit_code(&summary)
+        }
+        None if verbose => {
+            let summary = decoder.decode_paths_with(&paths, |_| VerboseLogger::new());
+            print_summary(&summary);
+            exit_code(&summary)
+        }
+        None => {
+            let summary = decoder.decode_paths(&paths);
+            print_summary(&summary);
+            exit_code(&summary)
+        }
+    })
+}
 
-    #[cfg(not(feature = "verbose-log"))]
-    {
-        paths.par_iter().for_each(|path| {
-            if let Err(e) = decode_file(path, escape_space, dry_run) {
-                eprintln!("ERROR processing {}: {}", path.display(), e);
-            }
-        });
+/// Print the totals and errors of `summary`.
+fn print_summary<L>(summary: &Summary<L>) {
+    let totals = summary.totals();
+    println!(
+        "Processed {} files, {} files changed.",
+        totals.processed(),
+        totals.changed
+    );
+
+    if totals.failed > 0 {
+        eprintln!("Failed to process {} files:", totals.failed);
+        for (path, e) in summary.errors() {
+            eprintln!("  {}: {}", path.display(), e);
+        }
+        if summary.cancelled {
+            eprintln!("Stopped early, the other files were not processed.");
+        }
     }
+}
 
-    Ok(())
+fn exit_code<L>(summary: &Summary<L>) -> ExitCode {
+    let totals = summary.totals();
+    if totals.failed > 0 {
+        ExitCode::from(EXIT_ERROR)
+    } else if totals.changed == 0 {
+        ExitCode::SUCCESS
+    } else if summary.dry_run {
+        ExitCode::from(EXIT_WOULD_CHANGE)
+    } else {
+        ExitCode::from(EXIT_CHANGED)
+    }
 }
 
 #[cfg(all(test, feature = "bin"))]
@@ -123,40 +379,10 @@ mod tests {
     use std::fs;
 
     use tempfile::TempDir;
-    use urldecoder::decode_str;
+    use urldecoder::{decode_str, log::NoOpLogger};
 
     use super::*;
 
-    #[test]
-    fn test_in_exclude() {
-        let pattern = PathBuf::from("path/to/file.txt");
-
-        // Case 1: Empty exclude should always return false
-        let exclude: Vec<PathBuf> = Vec::new();
-        assert!(!in_exclude(&exclude, &pattern));
-
-        // Case 2: Single path in exclude that matches the pattern
-        let exclude: Vec<PathBuf> = vec![PathBuf::from("path/to")];
-        assert!(in_exclude(&exclude, &pattern));
-
-        // Case 3: Single path in exclude that doesn't match the pattern
-        let exclude: Vec<PathBuf> = vec![PathBuf::from("other/path")];
-        assert!(!in_exclude(&exclude, &pattern));
-
-        // Case 4: Multiple paths in exclude, one of them matches the pattern
-        let exclude: Vec<PathBuf> = vec![PathBuf::from("path/to"), PathBuf::from("some/other")];
-        assert!(in_exclude(&exclude, &pattern));
-
-        // Case 5: Multiple paths in exclude, none of them matches the pattern
-        let exclude: Vec<PathBuf> = vec![PathBuf::from("/other/path"), PathBuf::from("some/other")];
-        assert!(!in_exclude(&exclude, &pattern));
-
-        // Case 6: Do not except files that only match prefix
-        let exclude: Vec<PathBuf> = vec![PathBuf::from("fi")];
-        let pattern = PathBuf::from("file.txt");
-        assert!(!in_exclude(&exclude, &pattern));
-    }
-
     #[test]
     fn exclude_and_recursive_test() {
         let test_str = "https://www.baidu.com/s?ie=UTF-8&wd=%E5%A4%A9%E6%B0%94";
@@ -171,37 +397,30 @@ mod tests {
         fs::write(&t2, test_str).unwrap();
         fs::write(&t3, test_str).unwrap();
 
-        process_directory(
-            vec![test_path.join("**/*").to_string_lossy().to_string()],
-            &[test_path.join("exclude.txt")],
-            false,
-            false,
-            #[cfg(feature = "verbose-log")]
-            false,
-        )
-        .unwrap();
-
-        assert_eq!(
-            fs::read_to_string(t1).unwrap(),
-            decode_str(
-                test_str,
+        let run = |dry_run| {
+            process_directory(
+                vec![test_path.join("**/*").to_string_lossy().to_string()],
+                &[test_path.join("exclude.txt")],
+                &DecodeOptions::default(),
+                dry_run,
+                false,
                 false,
-                #[cfg(feature = "verbose-log")]
-                false
+                None,
             )
             .unwrap()
-            .0
+        };
+        assert_eq!(run(true), ExitCode::from(EXIT_WOULD_CHANGE));
+        assert_eq!(fs::read_to_string(&t1).unwrap(), test_str);
+        assert_eq!(run(false), ExitCode::from(EXIT_CHANGED));
+        assert_eq!(run(false), ExitCode::SUCCESS);
+
+        assert_eq!(
+            fs::read_to_string(t1).unwrap(),
+            decode_str(test_str, false, &mut NoOpLogger).unwrap().0
         );
         assert_eq!(
             fs::read_to_string(t2).unwrap(),
-            decode_str(
-                test_str,
-                false,
-                #[cfg(feature = "verbose-log")]
-                false
-            )
-            .unwrap()
-            .0
+            decode_str(test_str, false, &mut NoOpLogger).unwrap().0
         );
         assert_eq!(fs::read_to_string(t3).unwrap(), test_str);
     }
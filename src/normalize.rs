@@ -0,0 +1,125 @@
+use memchr::{memchr, memchr2, memchr3, memrchr};
+
+use crate::detect::split_scheme;
+
+/// Normalize `url` into `out` following RFC 3986 §6.2.2 and §6.2.3:
+///
+/// - lowercase the scheme and host
+/// - remove the default port of `http` and `https`
+/// - resolve `.` and `..` path segments
+/// - remove an empty query
+///
+/// The output is never longer than `url`. Returns whether anything changed.
+pub(crate) fn normalize(url: &[u8], out: &mut Vec<u8>) -> bool {
+    let (scheme, authority_start) = split_scheme(url);
+    let authority_end = memchr3(b'/', b'?', b'#', &url[authority_start..])
+        .map_or(url.len(), |idx| authority_start + idx);
+    let path_end =
+        memchr2(b'?', b'#', &url[authority_end..]).map_or(url.len(), |idx| authority_end + idx);
+    let base = out.len();
+
+    out.extend(scheme.iter().map(u8::to_ascii_lowercase));
+    out.extend_from_slice(&url[scheme.len()..authority_start]);
+
+    // host, without userinfo and port
+    let authority = &url[authority_start..authority_end];
+    let host_start = memrchr(b'@', authority).map_or(0, |idx| idx + 1);
+    let host_end = match memrchr(b':', authority) {
+        // the colon of an IPv6 literal is not a port separator
+        Some(idx) if idx >= host_start && memchr(b']', &authority[idx..]).is_none() => idx,
+        _ => authority.len(),
+    };
+    out.extend_from_slice(&authority[..host_start]);
+    out.extend(
+        authority[host_start..host_end]
+            .iter()
+            .map(u8::to_ascii_lowercase),
+    );
+    let port = &authority[host_end..];
+    if !is_default_port(scheme, port) {
+        out.extend_from_slice(port);
+    }
+
+    remove_dot_segments(&url[authority_end..path_end], out);
+
+    let rest = &url[path_end..];
+    match rest.split_first() {
+        Some((b'?', after)) if after.is_empty() || after[0] == b'#' => out.extend_from_slice(after),
+        _ => out.extend_from_slice(rest),
+    }
+    out[base..] != *url
+}
+
+#[inline]
+fn is_default_port(scheme: &[u8], port: &[u8]) -> bool {
+    (scheme.eq_ignore_ascii_case(b"http") && port == b":80")
+        || (scheme.eq_ignore_ascii_case(b"https") && port == b":443")
+}
+
+/// Write the absolute `path` with its dot segments resolved (RFC 3986 §5.2.4)
+/// to `out`.
+fn remove_dot_segments(path: &[u8], out: &mut Vec<u8>) {
+    if path.is_empty() {
+        return;
+    }
+    let base = out.len();
+    let segments = path[1..].split(|&b| b == b'/');
+    let count = path[1..].iter().filter(|&&b| b == b'/').count() + 1;
+    for (i, segment) in segments.enumerate() {
+        let last = i + 1 == count;
+        match segment {
+            b"." => {
+                if last {
+                    out.push(b'/');
+                }
+            }
+            b".." => {
+                let parent = memrchr(b'/', &out[base..]).map_or(base, |idx| base + idx);
+                out.truncate(parent);
+                if last {
+                    out.push(b'/');
+                }
+            }
+            _ => {
+                out.push(b'/');
+                out.extend_from_slice(segment);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn norm(url: &str) -> (String, bool) {
+        let mut out = Vec::new();
+        let changed = normalize(url.as_bytes(), &mut out);
+        (String::from_utf8(out).unwrap(), changed)
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("http://Example.COM:80/", "http://example.com/"),
+            ("https://User@Example.com:443?", "https://User@example.com"),
+            ("https://x.com:8443/a/./b/../c", "https://x.com:8443/a/c"),
+            ("https://x.com/a/b/..", "https://x.com/a/"),
+            ("https://x.com/../../a/.", "https://x.com/a/"),
+            ("https://x.com/a/?#frag", "https://x.com/a/#frag"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(norm(input), (expected.to_owned(), true), "{input}");
+        }
+        for input in [
+            "https://x.com",
+            "https://x.com/a?",
+            "http://x.com:443/a?b",
+            "https://x.com/a?b=./..",
+            "https://x.com/a//b",
+        ] {
+            let expected = input.trim_end_matches('?');
+            assert_eq!(norm(input), (expected.to_owned(), input != expected));
+        }
+    }
+}
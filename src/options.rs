@@ -0,0 +1,233 @@
+use std::{fmt, sync::Arc};
+
+use crate::{comment::CommentSyntax, rewrite::RewriteRules};
+
+/// Upper bound of [`DecodeOptions::max_depth`].
+pub const MAX_DEPTH: usize = 8;
+
+/// Tracking parameters removed by `--strip-params`, see
+/// [`DecodeOptions::strip_params`].
+pub const DEFAULT_STRIP_PARAMS: &[&str] = &["utm_*", "fbclid", "gclid", "spm", "from"];
+
+/// Which percent-encoded sequences are decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecodePolicy {
+    /// Decode every escape.
+    #[default]
+    All,
+    /// Only decode runs of escapes forming complete non-ASCII UTF-8
+    /// characters, for readability. ASCII escapes such as `%2F` are kept as
+    /// written.
+    NonAsciiOnly,
+}
+
+/// What may precede `http://` or `https://` for it to start a URL.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemeBoundary {
+    /// Anything, so `xhttp://` holds a URL.
+    #[default]
+    Any,
+    /// Anything but a scheme character (ASCII alphanumeric, `+`, `-` or `.`),
+    /// so `myhttps://` and `git+https://` are not URLs.
+    Word,
+    /// Start of input, whitespace, a non-ASCII character, or one of
+    /// `` ([{<>"'`=:,;| ``.
+    Strict,
+}
+
+impl SchemeBoundary {
+    /// Whether a scheme may start after `prev`, `None` at start of input.
+    #[inline]
+    pub(crate) fn allows(self, prev: Option<u8>) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Word => {
+                prev.is_none_or(|b| !(b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.')))
+            }
+            Self::Strict => {
+                prev.is_none_or(|b| !b.is_ascii_graphic() || b"([{<>\"'`=:,;|".contains(&b))
+            }
+        }
+    }
+}
+
+/// Options of decoding.
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// Do not decode `%20` to space.
+    pub escape_space: bool,
+    /// Only decode URLs inside comments.
+    pub comments_only: bool,
+    /// Comment syntax used by `comments_only`. If `None`, [`decode_file`]
+    /// detects it by file extension, and in-memory decoding finds no comment.
+    ///
+    /// [`decode_file`]: crate::decode_file
+    pub comment_syntax: Option<CommentSyntax>,
+    /// Decode a URL repeatedly while it changes, for double-encoded URLs such
+    /// as `%2520`. At most [`MAX_DEPTH`] passes are done, and a pass that would
+    /// produce invalid UTF-8 is discarded. Default is 1.
+    pub max_depth: usize,
+    /// When a query parameter value holds a URL, such as the target of a
+    /// redirect link, keep `%25`, `%26`, `%23` and `%2B` encoded in it so that
+    /// the outer URL stays valid. See also [`nested_urls`](crate::nested_urls).
+    pub nested_urls: bool,
+    /// Which escapes are decoded.
+    pub policy: DecodePolicy,
+    /// Uppercase the hex digits of escapes that stay encoded, such as `%2f` to
+    /// `%2F`, as RFC 3986 recommends.
+    pub uppercase_escapes: bool,
+    /// Normalize URLs after decoding (RFC 3986 §6.2.2): lowercase the scheme
+    /// and host, remove default ports, resolve `.` and `..` path segments and
+    /// remove an empty query.
+    pub normalize: bool,
+    /// Remove query parameters whose name matches one of these patterns, such
+    /// as [`DEFAULT_STRIP_PARAMS`]. A pattern is a name, or a prefix followed
+    /// by `*`, matched case-insensitively. The query is dropped if no parameter
+    /// is left. Empty by default.
+    pub strip_params: Vec<String>,
+    /// Rules rewriting URLs after decoding, for link migrations. A rewritten
+    /// URL may be longer:
+    /// [`decode_in_place_with`](crate::decode_in_place_with) keeps URLs
+    /// that do not fit, [`decode_file`](crate::decode_file) writes
+    /// through a buffer instead.
+    pub rewrite: Option<Arc<RewriteRules>>,
+    /// Decide per URL what to write, after all other options applied. Like
+    /// [`rewrite`](Self::rewrite), a replacement may be longer than the URL.
+    pub url_hook: Option<UrlHook>,
+    /// Only decode URLs of these hosts, if not empty. A pattern is a host name,
+    /// or `*.` followed by a domain to match its subdomains,
+    /// case-insensitively.
+    pub only_hosts: Vec<String>,
+    /// Keep URLs of these hosts as written, with the patterns of
+    /// [`only_hosts`](Self::only_hosts).
+    pub skip_hosts: Vec<String>,
+    /// Also decode the non-standard `%uXXXX` escapes of IIS and JavaScript
+    /// `escape()`, including surrogate pairs. Invalid ones are kept as written.
+    pub percent_u: bool,
+    /// Also detect scheme-relative URLs such as `//cdn.example.com/a.png`.
+    pub detect_scheme_relative: bool,
+    /// Also detect bare domain URLs starting with `www.`.
+    ///
+    /// URLs without scheme must follow a word boundary, and have a domain name
+    /// followed by a path, query, fragment or port.
+    pub detect_www: bool,
+    /// Also detect `file://` URLs. They are decoded as file paths: `%2F` and
+    /// `%00`, which no path segment can hold, stay encoded.
+    pub file_urls: bool,
+    /// What may precede `http://` or `https://`.
+    pub scheme_boundary: SchemeBoundary,
+    /// Treat files as access logs: [`decode_file`](crate::decode_file) decodes
+    /// request paths and `Referer` fields with
+    /// [`decode_access_log`](crate::decode_access_log) instead of finding URLs.
+    /// Other options are ignored.
+    pub access_log: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            escape_space: false,
+            comments_only: false,
+            comment_syntax: None,
+            max_depth: 1,
+            nested_urls: false,
+            policy: DecodePolicy::All,
+            uppercase_escapes: false,
+            normalize: false,
+            strip_params: Vec::new(),
+            rewrite: None,
+            url_hook: None,
+            only_hosts: Vec::new(),
+            skip_hosts: Vec::new(),
+            percent_u: false,
+            detect_scheme_relative: false,
+            detect_www: false,
+            file_urls: false,
+            scheme_boundary: SchemeBoundary::Any,
+            access_log: false,
+        }
+    }
+}
+
+impl DecodeOptions {
+    /// Whether decoding may make the data longer.
+    #[inline]
+    pub(crate) fn may_grow(&self) -> bool {
+        self.rewrite.is_some() || self.url_hook.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn with_escape_space(escape_space: bool) -> Self {
+        Self {
+            escape_space,
+            ..Default::default()
+        }
+    }
+}
+
+/// A URL found by the scanner, passed to [`UrlHook`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct UrlContext<'a> {
+    /// The URL as written in the input.
+    pub raw: &'a [u8],
+    /// The URL as it would be written with the other options.
+    pub decoded: &'a [u8],
+    /// Byte offset of the URL in the input.
+    pub offset: usize,
+}
+
+/// What to write in place of a URL, returned by [`UrlHook`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlAction {
+    /// Write the raw URL.
+    Keep,
+    /// Write the decoded URL.
+    Decode,
+    /// Write these bytes instead.
+    Replace(Vec<u8>),
+}
+
+/// Per-URL callback of [`DecodeOptions::url_hook`].
+///
+/// ```
+/// use urldecoder::{DecodeOptions, UrlAction, UrlHook, decode_str_with, log::NoOpLogger};
+///
+/// let options = DecodeOptions {
+///     // keep the URLs of one host encoded
+///     url_hook: Some(UrlHook::new(|ctx| {
+///         if ctx.raw.starts_with(b"https://raw.example.com/") {
+///             UrlAction::Keep
+///         } else {
+///             UrlAction::Decode
+///         }
+///     })),
+///     ..Default::default()
+/// };
+/// let (res, _) = decode_str_with(
+///     "https://raw.example.com/%E4%B8%AD https://x.com/%E4%B8%AD",
+///     &options,
+///     &mut NoOpLogger,
+/// )
+/// .unwrap();
+/// assert_eq!(res, "https://raw.example.com/%E4%B8%AD https://x.com/中");
+/// ```
+#[derive(Clone)]
+pub struct UrlHook(Arc<dyn Fn(&UrlContext) -> UrlAction + Send + Sync>);
+
+impl UrlHook {
+    pub fn new(f: impl Fn(&UrlContext) -> UrlAction + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    #[inline]
+    pub(crate) fn call(&self, ctx: &UrlContext) -> UrlAction {
+        (self.0)(ctx)
+    }
+}
+
+impl fmt::Debug for UrlHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UrlHook(..)")
+    }
+}
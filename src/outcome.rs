@@ -0,0 +1,53 @@
+use std::fmt;
+
+/// What decoding did to a file, see [`decode_file`](crate::decode_file).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing to decode.
+    Unchanged,
+    /// The file changed, or would have outside a dry run.
+    Changed {
+        /// How many URLs changed. In access logs, how many fields.
+        urls: usize,
+        /// How much shorter the file got. Negative if rewrite rules made it
+        /// longer.
+        bytes_saved: i64,
+    },
+    /// The file was left alone without looking for URLs.
+    Skipped(SkipReason),
+}
+
+impl Outcome {
+    /// `Changed` for a file of `old_len` bytes decoded to `new_len`, if any
+    /// URL changed.
+    pub(crate) fn new(urls: usize, old_len: u64, new_len: u64) -> Self {
+        if urls == 0 {
+            return Self::Unchanged;
+        }
+        Self::Changed {
+            urls,
+            bytes_saved: old_len as i64 - new_len as i64,
+        }
+    }
+
+    pub fn is_changed(&self) -> bool {
+        matches!(self, Self::Changed { .. })
+    }
+}
+
+/// Why a file was skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// Only comments are decoded, and the file extension names no known
+    /// comment syntax.
+    UnknownCommentSyntax,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCommentSyntax => f.write_str("unknown comment syntax"),
+        }
+    }
+}
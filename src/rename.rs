@@ -0,0 +1,451 @@
+//! Decoding percent-encoded file and directory names, such as
+//! `%E5%9B%BE%E7%89%87.png` of downloaded files and static-site assets.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use memchr::memmem;
+use snafu::ResultExt;
+
+use crate::{
+    error::*,
+    transform::{ByteSet, FILE_KEEP, Rules, decode_component},
+};
+
+/// Bytes that a file name cannot hold.
+#[cfg(not(windows))]
+const NAME_KEEP: ByteSet = FILE_KEEP;
+#[cfg(windows)]
+const NAME_KEEP: ByteSet = FILE_KEEP
+    .with(b'\\')
+    .with(b':')
+    .with(b'*')
+    .with(b'?')
+    .with(b'"')
+    .with(b'<')
+    .with(b'>')
+    .with(b'|');
+
+/// Directories that are not walked.
+const SKIP_DIRS: [&str; 2] = [".git", "node_modules"];
+
+/// Extensions of files whose relative links may be updated.
+const LINK_FILE_EXTENSIONS: [&str; 4] = ["md", "markdown", "html", "htm"];
+
+/// Starts of link targets in Markdown and HTML.
+const LINK_STARTS: [&[u8]; 5] = [b"](", b"href=\"", b"src=\"", b"href='", b"src='"];
+
+/// A file or directory to rename.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Relative links updated in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkUpdate {
+    pub path: PathBuf,
+    pub links: usize,
+}
+
+/// The renames decoding the names under some paths, computed before anything
+/// is renamed.
+#[derive(Debug, Default)]
+pub struct RenamePlan {
+    /// Renames to apply, deepest first, so that the paths of the others stay
+    /// valid while applying them.
+    pub renames: Vec<Rename>,
+    /// Renames skipped because the decoded name already exists, or several
+    /// names of a directory decode to the same one.
+    pub collisions: Vec<Rename>,
+    /// New name of each renamed path.
+    new_names: HashMap<PathBuf, OsString>,
+    /// Files that may link to renamed ones.
+    link_files: Vec<PathBuf>,
+}
+
+impl RenamePlan {
+    /// Plan the renames of the files and directories under `roots`. A root
+    /// that is a file is renamed itself; `.git` and `node_modules` are not
+    /// walked.
+    pub fn new(roots: &[impl AsRef<Path>]) -> Result<Self> {
+        let mut plan = Self::default();
+        // file roots are renamed by directory, to detect collisions among them
+        let mut files: BTreeMap<&Path, Vec<PathBuf>> = BTreeMap::new();
+        for root in roots {
+            let root = root.as_ref();
+            if root.is_dir() {
+                plan.walk(root)?;
+            } else {
+                let parent = root.parent().unwrap_or(Path::new(""));
+                let siblings = files.entry(parent).or_default();
+                if !siblings.iter().any(|p| p == root) {
+                    siblings.push(root.to_path_buf());
+                    plan.add_link_file(root);
+                }
+            }
+        }
+        for (_, siblings) in files {
+            plan.add_entries(siblings);
+        }
+        plan.renames
+            .sort_by_key(|r| std::cmp::Reverse(r.from.components().count()));
+        Ok(plan)
+    }
+
+    fn walk(&mut self, dir: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir).context(ReadDirSnafu { path: dir })? {
+            entries.push(entry.context(ReadDirSnafu { path: dir })?.path());
+        }
+        entries.sort();
+        self.add_entries(entries.clone());
+        for path in entries {
+            let Ok(metadata) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if metadata.is_dir() {
+                let skip = path
+                    .file_name()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|name| SKIP_DIRS.contains(&name));
+                if !skip {
+                    self.walk(&path)?;
+                }
+            } else {
+                self.add_link_file(&path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Plan renames of sibling entries, detecting collisions among them.
+    fn add_entries(&mut self, entries: Vec<PathBuf>) {
+        let mut planned: Vec<Rename> = entries
+            .into_iter()
+            .filter_map(|from| {
+                let name = decode_name(from.file_name()?)?;
+                let to = from.with_file_name(name);
+                Some(Rename { from, to })
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        let duplicates: HashSet<PathBuf> = planned
+            .iter()
+            .filter(|r| !seen.insert(&r.to))
+            .map(|r| r.to.clone())
+            .collect();
+        for rename in planned.drain(..) {
+            if duplicates.contains(&rename.to) || fs::symlink_metadata(&rename.to).is_ok() {
+                self.collisions.push(rename);
+            } else {
+                self.new_names
+                    .insert(rename.from.clone(), rename.to.file_name().unwrap().into());
+                self.renames.push(rename);
+            }
+        }
+    }
+
+    fn add_link_file(&mut self, path: &Path) {
+        let is_link_file = path.extension().and_then(OsStr::to_str).is_some_and(|ext| {
+            LINK_FILE_EXTENSIONS
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(ext))
+        });
+        if is_link_file {
+            self.link_files.push(path.to_path_buf());
+        }
+    }
+
+    /// Update the relative links in Markdown and HTML files that point at
+    /// renamed files or directories, which must not have been renamed yet.
+    /// With `dry_run`, files are only read.
+    pub fn update_links(&self, dry_run: bool) -> Result<Vec<LinkUpdate>> {
+        let mut updates = Vec::new();
+        if self.renames.is_empty() {
+            return Ok(updates);
+        }
+        for path in &self.link_files {
+            let data = fs::read(path).context(OpenInputSnafu { path })?;
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let (output, links) = self.relink_file(dir, &data);
+            if links == 0 {
+                continue;
+            }
+            if !dry_run {
+                fs::write(path, output).context(WriteBackSnafu { path })?;
+            }
+            updates.push(LinkUpdate {
+                path: path.clone(),
+                links,
+            });
+        }
+        Ok(updates)
+    }
+
+    /// Rename the files and directories. Fails rather than overwrite a path
+    /// created since planning.
+    pub fn apply(&self) -> Result<()> {
+        for Rename { from, to } in &self.renames {
+            if fs::symlink_metadata(to).is_ok() {
+                return Err(io::Error::from(io::ErrorKind::AlreadyExists))
+                    .context(RenameSnafu { from, to });
+            }
+            fs::rename(from, to).context(RenameSnafu { from, to })?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the link targets of a file in `dir`. Returns the new content and
+    /// how many links changed.
+    fn relink_file(&self, dir: &Path, data: &[u8]) -> (Vec<u8>, usize) {
+        let mut targets = Vec::new();
+        for start in LINK_STARTS {
+            let end_bytes: &[u8] = match start.last() {
+                Some(b'(') => b") \t\r\n",
+                Some(&quote) => &[quote, b'\n'],
+                None => unreachable!(),
+            };
+            for idx in memmem::find_iter(data, start) {
+                let target_start = idx + start.len();
+                let len = data[target_start..]
+                    .iter()
+                    .position(|b| end_bytes.contains(b))
+                    .unwrap_or(data.len() - target_start);
+                targets.push((target_start, target_start + len));
+            }
+        }
+        targets.sort_unstable();
+
+        let mut output = Vec::with_capacity(data.len());
+        let mut links = 0;
+        let mut copied = 0;
+        for (start, end) in targets {
+            if start < copied {
+                continue;
+            }
+            let Some(target) = std::str::from_utf8(&data[start..end])
+                .ok()
+                .and_then(|target| self.relink(dir, target))
+            else {
+                continue;
+            };
+            output.extend_from_slice(&data[copied..start]);
+            output.extend_from_slice(target.as_bytes());
+            copied = end;
+            links += 1;
+        }
+        output.extend_from_slice(&data[copied..]);
+        (output, links)
+    }
+
+    /// The link `target` of a file in `dir` with renamed segments replaced, if
+    /// any. A segment may name a file as written or percent-decoded.
+    fn relink(&self, dir: &Path, target: &str) -> Option<String> {
+        if target.is_empty() || target.starts_with(['/', '#']) || has_scheme(target) {
+            return None;
+        }
+        let path_end = target.find(['?', '#']).unwrap_or(target.len());
+        let (path, suffix) = target.split_at(path_end);
+
+        let mut cur = dir.to_path_buf();
+        // whether `cur` is the path named by the segments so far
+        let mut resolved = true;
+        let mut changed = false;
+        let mut output = String::with_capacity(target.len());
+        for (idx, segment) in path.split('/').enumerate() {
+            if idx > 0 {
+                output.push('/');
+            }
+            if !resolved || segment.is_empty() || segment == "." {
+                output.push_str(segment);
+                continue;
+            }
+            if segment == ".." {
+                output.push_str(segment);
+                // resolve lexically, so that `cur` keeps matching planned paths
+                if matches!(cur.components().next_back(), Some(Component::Normal(_))) {
+                    cur.pop();
+                } else {
+                    resolved = false;
+                }
+                continue;
+            }
+            let decoded = decode_segment(segment);
+            let candidates = [Some(segment), decoded.as_deref()];
+            let mut candidates = candidates.into_iter().flatten();
+            if let Some((name, new_name)) = candidates
+                .clone()
+                .find_map(|name| Some((name, self.new_names.get(&cur.join(name))?)))
+            {
+                encode_segment(&new_name.to_string_lossy(), &mut output);
+                cur.push(name);
+                changed = true;
+            } else if let Some(name) = candidates.find(|name| cur.join(name).exists()) {
+                output.push_str(segment);
+                cur.push(name);
+            } else {
+                output.push_str(segment);
+                resolved = false;
+            }
+        }
+        output.push_str(suffix);
+        changed.then_some(output)
+    }
+}
+
+/// The decoded file name, if it has escapes to decode and stays UTF-8.
+fn decode_name(name: &OsStr) -> Option<OsString> {
+    let name = name.to_str()?;
+    let mut out = Vec::with_capacity(name.len());
+    if !decode_component(name.as_bytes(), Rules::ALL.keeping(NAME_KEEP), &mut out) {
+        return None;
+    }
+    String::from_utf8(out).ok().map(Into::into)
+}
+
+fn decode_segment(segment: &str) -> Option<String> {
+    let mut out = Vec::with_capacity(segment.len());
+    decode_component(segment.as_bytes(), Rules::ALL, &mut out)
+        .then(|| String::from_utf8(out).ok())
+        .flatten()
+}
+
+/// Append a file name to a link, encoding the bytes that would end it or be
+/// taken as an escape, query or fragment.
+pub(crate) fn encode_segment(name: &str, out: &mut String) {
+    for ch in name.chars() {
+        if ch.is_ascii_control() || " \"#%'()<>?".contains(ch) {
+            out.push_str(&format!("%{:02X}", ch as u8));
+        } else {
+            out.push(ch);
+        }
+    }
+}
+
+/// Whether a link target starts with a URL scheme such as `https:`.
+fn has_scheme(target: &str) -> bool {
+    target.find(':').is_some_and(|idx| {
+        target[..idx]
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_rename_plan() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let assets = root.join("%E5%9B%BE");
+        fs::create_dir(&assets).unwrap();
+        fs::write(assets.join("a%20b.png"), "").unwrap();
+        fs::write(assets.join("x%2Fy.png"), "").unwrap();
+        fs::write(root.join("c%2Ed"), "").unwrap();
+        fs::write(root.join("c.d"), "").unwrap();
+        fs::write(
+            root.join("index.md"),
+            "![](%E5%9B%BE/a%20b.png) [x](%25E5%259B%25BE/x%2Fy.png#top) [c](c%2Ed) \
+             <img src=\"./%E5%9B%BE/a%20b.png\"> [y](https://a.com/%E5%9B%BE/a%20b.png)",
+        )
+        .unwrap();
+
+        let plan = RenamePlan::new(&[root]).unwrap();
+        let renamed: Vec<_> = plan
+            .renames
+            .iter()
+            .map(|r| {
+                let from = r.from.strip_prefix(root).unwrap();
+                let to = r.to.strip_prefix(root).unwrap();
+                (from.to_str().unwrap(), to.to_str().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            renamed,
+            [
+                ("%E5%9B%BE/a%20b.png", "%E5%9B%BE/a b.png"),
+                ("%E5%9B%BE", "图"),
+            ]
+        );
+        assert_eq!(plan.collisions.len(), 1);
+        assert_eq!(plan.collisions[0].from, root.join("c%2Ed"));
+
+        let updates = plan.update_links(false).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].links, 3);
+        plan.apply().unwrap();
+        assert!(root.join("图/a b.png").is_file());
+        assert!(root.join("图/x%2Fy.png").is_file());
+        assert_eq!(
+            fs::read_to_string(root.join("index.md")).unwrap(),
+            "![](图/a%20b.png) [x](图/x%2Fy.png#top) [c](c%2Ed) \
+             <img src=\"./图/a%20b.png\"> [y](https://a.com/%E5%9B%BE/a%20b.png)"
+        );
+    }
+
+    #[test]
+    fn test_rename_file_roots() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let (upper, lower) = (root.join("%E4%B8%AD.txt"), root.join("%e4%b8%ad.txt"));
+        fs::write(&upper, "one").unwrap();
+        fs::write(&lower, "two").unwrap();
+        let other = root.join("a%20b.txt");
+        fs::write(&other, "").unwrap();
+
+        let plan = RenamePlan::new(&[&upper, &lower, &other]).unwrap();
+        assert_eq!(plan.collisions.len(), 2);
+        assert_eq!(plan.renames.len(), 1);
+        plan.apply().unwrap();
+        assert_eq!(fs::read_to_string(&upper).unwrap(), "one");
+        assert_eq!(fs::read_to_string(&lower).unwrap(), "two");
+        assert!(root.join("a b.txt").is_file());
+
+        // a target created after planning is not overwritten
+        fs::write(root.join("a%20c.txt"), "three").unwrap();
+        let plan = RenamePlan::new(&[root.join("a%20c.txt")]).unwrap();
+        fs::write(root.join("a c.txt"), "four").unwrap();
+        assert!(matches!(plan.apply(), Err(Error::Rename { .. })));
+        assert_eq!(fs::read_to_string(root.join("a c.txt")).unwrap(), "four");
+    }
+
+    #[test]
+    fn test_relink_parent() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let docs = root.join("docs");
+        fs::create_dir(&docs).unwrap();
+        fs::write(root.join("%E5%9B%BE.png"), "").unwrap();
+        fs::write(
+            docs.join("a.md"),
+            "![](../%E5%9B%BE.png) [x](../../%E5%9B%BE.png)",
+        )
+        .unwrap();
+        fs::write(root.join("b.md"), "![](./docs/../%E5%9B%BE.png)").unwrap();
+
+        let plan = RenamePlan::new(&[root]).unwrap();
+        let updates = plan.update_links(false).unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(
+            fs::read_to_string(docs.join("a.md")).unwrap(),
+            "![](../图.png) [x](../../%E5%9B%BE.png)"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("b.md")).unwrap(),
+            "![](./docs/../图.png)"
+        );
+
+        // no component left to go above
+        assert_eq!(plan.relink(Path::new(""), "../%E5%9B%BE.png"), None);
+    }
+}
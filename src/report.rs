@@ -0,0 +1,525 @@
+//! Machine-readable reports of a [`Summary`].
+//!
+//! Each file is an object with its `path` and `status`, one of `unchanged`,
+//! `changed`, `skipped` or `error`:
+//!
+//! ```json
+//! {"path":"a.md","status":"changed","urls":1,"bytes_saved":12,"changes":[{"offset":4,"line":1,"column":5,"original":"https://x.com/%E5%A4%A9","decoded":"https://x.com/天","depth":1}]}
+//! {"path":"b.txt","status":"skipped","reason":"unknown comment syntax"}
+//! {"path":"c.md","status":"error","error":{"kind":"OpenInput","message":"Failed to open input file c.md: ..."}}
+//! ```
+//!
+//! The totals count files by status, and sum `urls` and `bytes_saved`.
+//!
+//! [`Format::Sarif`] instead reports each changed URL as a result for code
+//! scanning, see [`write_report`].
+
+use std::{
+    borrow::Cow,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    Outcome,
+    batch::{FileReport, Summary, Totals},
+    log::Change,
+    rename::encode_segment,
+};
+
+/// Layout of a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object with `files`, `totals`, `dry_run` and `cancelled`.
+    Json,
+    /// One line per file, then a last line with `totals`, `dry_run` and
+    /// `cancelled`. Lines carry a `type` of `file` or `summary`.
+    JsonLines,
+    /// A SARIF 2.1.0 log with one result per changed URL.
+    Sarif,
+}
+
+/// Write a report of `summary` in `format`.
+///
+/// A SARIF result locates the URL by line and column in UTF-16 code units as
+/// well as by byte, and carries a fix replacing it with the decoded one,
+/// unless the decoded bytes are not valid UTF-8 and cannot be written as a
+/// fix. The regions locate the URLs as written before decoding, so outside a
+/// dry run they no longer match the files. Files that failed are reported as
+/// notifications of the invocation.
+pub fn write_report(
+    out: &mut impl Write,
+    summary: &Summary<Vec<Change>>,
+    format: Format,
+) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            let report = Report {
+                files: summary.files.iter().map(FileEntry::new).collect(),
+                end: End::new(summary),
+            };
+            serde_json::to_writer(&mut *out, &report)?;
+            out.write_all(b"\n")
+        }
+        Format::JsonLines => {
+            for file in &summary.files {
+                serde_json::to_writer(&mut *out, &Line::File(FileEntry::new(file)))?;
+                out.write_all(b"\n")?;
+            }
+            serde_json::to_writer(&mut *out, &Line::Summary(End::new(summary)))?;
+            out.write_all(b"\n")
+        }
+        Format::Sarif => {
+            serde_json::to_writer(&mut *out, &SarifLog::new(summary))?;
+            out.write_all(b"\n")
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    files: Vec<FileEntry<'a>>,
+    #[serde(flatten)]
+    end: End,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Line<'a> {
+    File(FileEntry<'a>),
+    Summary(End),
+}
+
+#[derive(Serialize)]
+struct FileEntry<'a> {
+    path: Cow<'a, str>,
+    #[serde(flatten)]
+    status: Status<'a>,
+}
+
+impl<'a> FileEntry<'a> {
+    fn new(file: &'a FileReport<Vec<Change>>) -> Self {
+        let status = match &file.result {
+            Ok(Outcome::Unchanged) => Status::Unchanged,
+            Ok(Outcome::Changed { urls, bytes_saved }) => Status::Changed {
+                urls: *urls,
+                bytes_saved: *bytes_saved,
+                changes: file.logger.iter().map(ChangeEntry::from).collect(),
+            },
+            Ok(Outcome::Skipped(reason)) => Status::Skipped {
+                reason: reason.to_string(),
+            },
+            Err(e) => Status::Error {
+                error: ErrorEntry {
+                    kind: e.kind(),
+                    message: e.to_string(),
+                },
+            },
+        };
+        Self {
+            path: file.path.to_string_lossy(),
+            status,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Status<'a> {
+    Unchanged,
+    Changed {
+        urls: usize,
+        bytes_saved: i64,
+        changes: Vec<ChangeEntry<'a>>,
+    },
+    Skipped {
+        reason: String,
+    },
+    Error {
+        error: ErrorEntry,
+    },
+}
+
+#[derive(Serialize)]
+struct ChangeEntry<'a> {
+    offset: usize,
+    line: usize,
+    column: usize,
+    original: &'a str,
+    decoded: &'a str,
+    depth: usize,
+}
+
+impl<'a> From<&'a Change> for ChangeEntry<'a> {
+    fn from(change: &'a Change) -> Self {
+        Self {
+            offset: change.offset,
+            line: change.line,
+            column: change.column,
+            original: &change.original,
+            decoded: &change.decoded,
+            depth: change.depth,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorEntry {
+    kind: &'static str,
+    message: String,
+}
+
+/// The members after the files.
+#[derive(Serialize)]
+struct End {
+    totals: Totals,
+    dry_run: bool,
+    cancelled: bool,
+}
+
+impl End {
+    fn new<L>(summary: &Summary<L>) -> Self {
+        Self {
+            totals: summary.totals(),
+            dry_run: summary.dry_run,
+            cancelled: summary.cancelled,
+        }
+    }
+}
+
+const SARIF_RULE: &str = "encoded-url";
+
+#[derive(Serialize)]
+struct SarifLog {
+    version: &'static str,
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    runs: [Run; 1],
+}
+
+impl SarifLog {
+    fn new(summary: &Summary<Vec<Change>>) -> Self {
+        let mut results = Vec::new();
+        for file in &summary.files {
+            if !matches!(file.result, Ok(Outcome::Changed { .. })) {
+                continue;
+            }
+            let uri = path_uri(&file.path);
+            results.extend(file.logger.iter().map(|c| SarifResult::new(&uri, c)));
+        }
+        let notifications = summary
+            .errors()
+            .map(|(path, e)| Notification {
+                level: "error",
+                message: Message::new(e.to_string()),
+                descriptor: Descriptor { id: e.kind() },
+                locations: [Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation::new(path_uri(path)),
+                        region: None,
+                    },
+                }],
+            })
+            .collect();
+
+        Self {
+            version: "2.1.0",
+            schema: "https://json.schemastore.org/sarif-2.1.0.json",
+            runs: [Run {
+                tool: Tool {
+                    driver: Driver {
+                        name: "urldecoder",
+                        version: env!("CARGO_PKG_VERSION"),
+                        information_uri: env!("CARGO_PKG_REPOSITORY"),
+                        rules: [Rule {
+                            id: SARIF_RULE,
+                            name: "EncodedUrl",
+                            short_description: Message::new("URL can be decoded"),
+                            full_description: Message::new(
+                                "The URL has percent-encoded characters that read better decoded.",
+                            ),
+                        }],
+                    },
+                },
+                results,
+                invocations: [Invocation {
+                    execution_successful: summary.totals().failed == 0,
+                    tool_execution_notifications: notifications,
+                }],
+            }],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Run {
+    tool: Tool,
+    results: Vec<SarifResult>,
+    invocations: [Invocation; 1],
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: [Rule; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    name: &'static str,
+    short_description: Message,
+    full_description: Message,
+}
+
+#[derive(Serialize)]
+struct Message {
+    text: Cow<'static, str>,
+}
+
+impl Message {
+    fn new(text: impl Into<Cow<'static, str>>) -> Self {
+        Self { text: text.into() }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: &'static str,
+    level: &'static str,
+    message: Message,
+    locations: [Location; 1],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fixes: Option<[Fix; 1]>,
+}
+
+impl SarifResult {
+    fn new(uri: &str, change: &Change) -> Self {
+        // SARIF counts UTF-16 code units by default. URL characters are ASCII,
+        // so only `decoded` may be lossy.
+        let start_column = change.column_utf16;
+        let region = Region {
+            start_line: change.line,
+            start_column,
+            end_line: change.line,
+            end_column: start_column + change.original.encode_utf16().count(),
+            byte_offset: change.offset,
+            byte_length: change.original.len(),
+        };
+        Self {
+            rule_id: SARIF_RULE,
+            level: "warning",
+            message: Message::new(format!(
+                "`{}` can be decoded to `{}`",
+                change.original, change.decoded
+            )),
+            locations: [Location {
+                physical_location: PhysicalLocation {
+                    artifact_location: ArtifactLocation::new(uri.to_owned()),
+                    region: Some(region),
+                },
+            }],
+            fixes: (!change.lossy).then(|| {
+                [Fix {
+                    description: Message::new("Decode the URL"),
+                    artifact_changes: [ArtifactChange {
+                        artifact_location: ArtifactLocation::new(uri.to_owned()),
+                        replacements: [Replacement {
+                            deleted_region: region,
+                            inserted_content: Message::new(change.decoded.clone()),
+                        }],
+                    }],
+                }]
+            }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+impl ArtifactLocation {
+    fn new(uri: String) -> Self {
+        Self { uri }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+    byte_offset: usize,
+    byte_length: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Fix {
+    description: Message,
+    artifact_changes: [ArtifactChange; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactChange {
+    artifact_location: ArtifactLocation,
+    replacements: [Replacement; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Replacement {
+    deleted_region: Region,
+    inserted_content: Message,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Invocation {
+    execution_successful: bool,
+    tool_execution_notifications: Vec<Notification>,
+}
+
+#[derive(Serialize)]
+struct Notification {
+    level: &'static str,
+    message: Message,
+    descriptor: Descriptor,
+    locations: [Location; 1],
+}
+
+#[derive(Serialize)]
+struct Descriptor {
+    id: &'static str,
+}
+
+/// A relative URI reference for a relative path, a `file` URI for an absolute
+/// one.
+fn path_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::with_capacity(path.len());
+    if Path::new(&path).is_absolute() || path.starts_with('/') {
+        uri.push_str(if path.starts_with('/') {
+            "file://"
+        } else {
+            "file:///"
+        });
+    }
+    for (idx, segment) in path.split('/').enumerate() {
+        if idx > 0 {
+            uri.push('/');
+        }
+        encode_segment(segment, &mut uri);
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{DecodeOptions, batch::BatchDecoder};
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        fs::write(&a, "x https://x.com/%E4%B8%AD").unwrap();
+        let paths = [a, dir.path().join("missing.txt")];
+
+        let options = DecodeOptions::default();
+        let summary = BatchDecoder::new(&options)
+            .dry_run(true)
+            .decode_paths_with(&paths, |_| Vec::new());
+        let mut out = Vec::new();
+        write_report(&mut out, &summary, Format::JsonLines).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(
+            r#""status":"changed","urls":1,"bytes_saved":6,"changes":[{"offset":2,"line":1,"column":3,"original":"https://x.com/%E4%B8%AD","decoded":"https://x.com/中","depth":1}]}"#
+        ));
+        assert!(lines[1].contains(r#""status":"error","error":{"kind":"ReadInput","#));
+        assert_eq!(
+            lines[2],
+            r#"{"type":"summary","totals":{"unchanged":0,"changed":1,"skipped":0,"failed":1,"urls":1,"bytes_saved":6},"dry_run":true,"cancelled":false}"#
+        );
+    }
+
+    #[test]
+    fn test_write_sarif() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a b.md");
+        fs::write(
+            &a,
+            "x\n中 https://x.com/%E4%B8%AD https://x.com/%E6%96%87\nhttps://x.com/%FF",
+        )
+        .unwrap();
+        let paths = [a, dir.path().join("missing.md")];
+
+        let options = DecodeOptions::default();
+        let summary = BatchDecoder::new(&options)
+            .dry_run(true)
+            .decode_paths_with(&paths, |_| Vec::new());
+        let mut out = Vec::new();
+        write_report(&mut out, &summary, Format::Sarif).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(out.matches(r#""ruleId":"encoded-url""#).count(), 3);
+        // no fix inserting U+FFFD for the invalid UTF-8 of `%FF`
+        assert_eq!(out.matches(r#""fixes""#).count(), 2);
+        assert!(!out.contains("\"text\":\"https://x.com/\u{FFFD}\"}"));
+        assert_eq!(
+            out.matches(r#"{"startLine":2,"startColumn":3,"endLine":2,"endColumn":26,"byteOffset":6,"byteLength":23}"#)
+                .count(),
+            2
+        );
+        assert!(out.contains(
+            r#"{"startLine":2,"startColumn":27,"endLine":2,"endColumn":50,"byteOffset":30,"byteLength":23}"#
+        ));
+        assert!(out.contains(r#"a%20b.md"}"#));
+        assert!(out.contains(r#""insertedContent":{"text":"https://x.com/中"}"#));
+        assert!(out.contains(r#""executionSuccessful":false"#));
+        assert!(out.contains(r#""descriptor":{"id":"ReadInput"}"#));
+    }
+
+    #[test]
+    fn test_path_uri() {
+        assert_eq!(path_uri(Path::new("docs/a b#1.md")), "docs/a%20b%231.md");
+        #[cfg(unix)]
+        assert_eq!(path_uri(Path::new("/tmp/文.md")), "file:///tmp/文.md");
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use snafu::{ResultExt, ensure};
+
+use crate::error::*;
+
+/// A rule rewriting the URLs it matches.
+#[derive(Debug)]
+pub enum RewriteRule {
+    /// Replace the leading `from` of a URL by `to`.
+    Prefix { from: String, to: String },
+    /// Replace the first match of a regex by `to`, which may refer to capture
+    /// groups like `$1`.
+    #[cfg(feature = "regex")]
+    Regex { re: regex::bytes::Regex, to: String },
+}
+
+impl fmt::Display for RewriteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prefix { from, to } => write!(f, "prefix {from} {to}"),
+            #[cfg(feature = "regex")]
+            Self::Regex { re, to } => write!(f, "regex {re} {to}"),
+        }
+    }
+}
+
+/// Ordered URL rewrite rules, for link migrations. The first matching rule is
+/// applied to each URL after decoding, and the times each rule fired are
+/// counted.
+///
+/// Rules files hold one rule per line, `#` starts a comment line:
+///
+/// ```text
+/// # kind    from                          to
+/// prefix    https://old.example.com/docs/ https://docs.example.com/
+/// regex     ^https://x\.com/u/(\d+)       https://x.com/user/$1
+/// ```
+///
+/// `regex` rules need the `regex` feature.
+#[derive(Debug, Default)]
+pub struct RewriteRules {
+    rules: Vec<RewriteRule>,
+    hits: Vec<AtomicUsize>,
+}
+
+impl RewriteRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, rule: RewriteRule) {
+        self.rules.push(rule);
+        self.hits.push(AtomicUsize::new(0));
+    }
+
+    /// Parse rules from the text of a rules file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Self::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            ensure!(
+                fields.len() == 3,
+                InvalidRewriteRuleSnafu {
+                    line: line_no,
+                    reason: "expected `<kind> <from> <to>`",
+                }
+            );
+            let (from, to) = (fields[1], fields[2].to_owned());
+            let rule = match fields[0] {
+                "prefix" => RewriteRule::Prefix {
+                    from: from.to_owned(),
+                    to,
+                },
+                #[cfg(feature = "regex")]
+                "regex" => RewriteRule::Regex {
+                    re: regex::bytes::Regex::new(from)
+                        .context(InvalidRegexSnafu { line: line_no })?,
+                    to,
+                },
+                kind => {
+                    return InvalidRewriteRuleSnafu {
+                        line: line_no,
+                        reason: format!("unknown rule kind `{kind}`"),
+                    }
+                    .fail();
+                }
+            };
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+
+    /// Read and parse a rules file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).context(OpenInputSnafu { path })?;
+        Self::parse(&text)
+    }
+
+    /// Each rule with the times it fired.
+    pub fn hits(&self) -> impl Iterator<Item = (&RewriteRule, usize)> {
+        self.rules
+            .iter()
+            .zip(&self.hits)
+            .map(|(rule, hits)| (rule, hits.load(Ordering::Relaxed)))
+    }
+
+    /// Write `url` rewritten by the first matching rule to `out`. Returns the
+    /// index of the rule that fired, to [`count_hit`](Self::count_hit) once
+    /// its output is kept.
+    pub(crate) fn apply(&self, url: &[u8], out: &mut Vec<u8>) -> Option<usize> {
+        for (idx, rule) in self.rules.iter().enumerate() {
+            let fired = match rule {
+                RewriteRule::Prefix { from, to } => match url.strip_prefix(from.as_bytes()) {
+                    Some(rest) => {
+                        out.extend_from_slice(to.as_bytes());
+                        out.extend_from_slice(rest);
+                        true
+                    }
+                    None => false,
+                },
+                #[cfg(feature = "regex")]
+                RewriteRule::Regex { re, to } => match re.captures(url) {
+                    Some(caps) => {
+                        let m = caps.get(0).unwrap();
+                        out.extend_from_slice(&url[..m.start()]);
+                        caps.expand(to.as_bytes(), out);
+                        out.extend_from_slice(&url[m.end()..]);
+                        true
+                    }
+                    None => false,
+                },
+            };
+            if fired {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    #[inline]
+    pub(crate) fn count_hit(&self, rule: usize) {
+        self.hits[rule].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_rules() {
+        let rules = RewriteRules::parse(
+            "# migrate docs\n\
+             prefix https://old.com/docs/ https://new.com/\n\
+             \n\
+             prefix https://old.com/ https://new.com/old/\n",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        assert_eq!(rules.apply(b"https://old.com/docs/a", &mut out), Some(0));
+        assert_eq!(out, b"https://new.com/a");
+        out.clear();
+        assert_eq!(rules.apply(b"https://old.com/b", &mut out), Some(1));
+        assert_eq!(out, b"https://new.com/old/b");
+        out.clear();
+        assert_eq!(rules.apply(b"https://other.com/", &mut out), None);
+        rules.count_hit(1);
+        assert_eq!(rules.hits().map(|(_, n)| n).collect::<Vec<_>>(), [0, 1]);
+
+        assert!(matches!(
+            RewriteRules::parse("prefix https://a.com/"),
+            Err(Error::InvalidRewriteRule { line: 1, .. })
+        ));
+        assert!(matches!(
+            RewriteRules::parse("\nglob * *"),
+            Err(Error::InvalidRewriteRule { line: 2, .. })
+        ));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_rule() {
+        let rules =
+            RewriteRules::parse(r"regex ^https://x\.com/u/(\d+) https://x.com/user/$1").unwrap();
+        let mut out = Vec::new();
+        assert_eq!(rules.apply(b"https://x.com/u/42?tab=1", &mut out), Some(0));
+        assert_eq!(out, b"https://x.com/user/42?tab=1");
+    }
+}
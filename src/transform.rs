@@ -0,0 +1,469 @@
+use memchr::memchr;
+
+use crate::{
+    DecodeOptions, DecodePolicy, MAX_DEPTH, UrlAction, UrlContext, decode_hex_pair,
+    normalize::normalize, push_escape,
+};
+
+/// Set of bytes, used for the bytes that must stay percent-encoded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteSet([u32; 8]);
+
+impl ByteSet {
+    pub(crate) const EMPTY: Self = Self([0; 8]);
+
+    pub(crate) const fn with(mut self, byte: u8) -> Self {
+        let idx = byte as usize;
+        self.0[idx >> 5] |= 1u32 << (idx & 31);
+        self
+    }
+
+    pub(crate) const fn union(mut self, other: Self) -> Self {
+        let mut i = 0;
+        while i < 8 {
+            self.0[i] |= other.0[i];
+            i += 1;
+        }
+        self
+    }
+
+    #[inline(always)]
+    pub(crate) fn contains(&self, byte: u8) -> bool {
+        let idx = byte as usize;
+        (self.0[idx >> 5] >> (idx & 31)) & 1 == 1
+    }
+}
+
+/// Bytes that would change the meaning of the outer query if a nested URL in
+/// a parameter value had them decoded.
+const NESTED_KEEP: ByteSet = ByteSet::EMPTY.with(b'%').with(b'&').with(b'#').with(b'+');
+
+/// Bytes that a segment of a file path cannot hold.
+pub(crate) const FILE_KEEP: ByteSet = ByteSet::EMPTY.with(b'/').with(0);
+
+/// How escapes of a URL component are decoded.
+#[derive(Clone, Copy)]
+pub(crate) struct Rules {
+    /// Bytes that stay encoded.
+    keep: ByteSet,
+    /// Only decode escapes forming complete non-ASCII UTF-8 characters.
+    non_ascii_only: bool,
+    /// Uppercase the hex digits of escapes that stay encoded.
+    uppercase: bool,
+    /// Decode `%uXXXX` escapes.
+    percent_u: bool,
+}
+
+impl Rules {
+    /// Decode everything.
+    pub(crate) const ALL: Self = Self {
+        keep: ByteSet::EMPTY,
+        non_ascii_only: false,
+        uppercase: false,
+        percent_u: false,
+    };
+
+    #[inline]
+    pub(crate) fn keeping(self, keep: ByteSet) -> Self {
+        Self {
+            keep: self.keep.union(keep),
+            ..self
+        }
+    }
+}
+
+/// Per-URL decoding with options that the fast path does not handle.
+pub(crate) struct Transformer<'a> {
+    options: &'a DecodeOptions,
+    cur: Vec<u8>,
+    next: Vec<u8>,
+    depth: usize,
+    /// The rewrite rule that fired for the last URL.
+    rule: Option<usize>,
+}
+
+impl<'a> Transformer<'a> {
+    /// Returns `None` if the fast path can handle `options`.
+    pub(crate) fn new(options: &'a DecodeOptions) -> Option<Self> {
+        (options.max_depth > 1
+            || options.nested_urls
+            || options.policy != DecodePolicy::All
+            || options.uppercase_escapes
+            || options.percent_u
+            || options.file_urls
+            || options.normalize
+            || !options.strip_params.is_empty()
+            || options.rewrite.is_some()
+            || options.url_hook.is_some())
+        .then(|| Self {
+            options,
+            cur: Vec::new(),
+            next: Vec::new(),
+            depth: 0,
+            rule: None,
+        })
+    }
+
+    /// Transform `url`, found at `offset` of the input, into [`Self::output`].
+    /// `delim` is the quote enclosing the URL. Returns whether the URL changed.
+    pub(crate) fn transform(&mut self, url: &[u8], delim: Option<u8>, offset: usize) -> bool {
+        let raw = url;
+        let mut keep = ByteSet::EMPTY;
+        if self.options.escape_space {
+            keep = keep.with(b' ');
+        }
+        if let Some(delim) = delim {
+            keep = keep.with(delim);
+        }
+        if self.options.file_urls && is_file_url(url) {
+            keep = keep.union(FILE_KEEP);
+        }
+        let rules = Rules {
+            keep,
+            non_ascii_only: self.options.policy == DecodePolicy::NonAsciiOnly,
+            uppercase: self.options.uppercase_escapes,
+            percent_u: self.options.percent_u,
+        };
+        let nested = self.options.nested_urls;
+
+        // strip before decoding, while the query structure is intact
+        self.next.clear();
+        let stripped = !self.options.strip_params.is_empty()
+            && strip_params(url, &self.options.strip_params, &mut self.next);
+        let url = if stripped { &self.next[..] } else { url };
+
+        self.cur.clear();
+        self.depth = usize::from(decode_pass(url, rules, nested, &mut self.cur));
+
+        // iterative decoding: stop once a pass changes nothing or would produce
+        // invalid UTF-8
+        let max_depth = self.options.max_depth.min(MAX_DEPTH);
+        while self.depth > 0 && self.depth < max_depth && memchr(b'%', &self.cur).is_some() {
+            self.next.clear();
+            if !decode_pass(&self.cur, rules, nested, &mut self.next)
+                || simdutf8::basic::from_utf8(&self.next).is_err()
+            {
+                break;
+            }
+            std::mem::swap(&mut self.cur, &mut self.next);
+            self.depth += 1;
+        }
+
+        let mut normalized = false;
+        if self.options.normalize {
+            self.next.clear();
+            normalized = normalize(&self.cur, &mut self.next);
+            std::mem::swap(&mut self.cur, &mut self.next);
+        }
+        let mut rewritten = false;
+        self.rule = None;
+        if let Some(rules) = &self.options.rewrite {
+            self.next.clear();
+            self.rule = rules.apply(&self.cur, &mut self.next);
+            if self.rule.is_some() {
+                rewritten = self.next != self.cur;
+                std::mem::swap(&mut self.cur, &mut self.next);
+            }
+        }
+        let changed = self.depth > 0 || stripped || normalized || rewritten;
+
+        let Some(hook) = &self.options.url_hook else {
+            return changed;
+        };
+        let ctx = UrlContext {
+            raw,
+            decoded: &self.cur,
+            offset,
+        };
+        match hook.call(&ctx) {
+            UrlAction::Decode => changed,
+            UrlAction::Keep => {
+                self.cur.clear();
+                self.cur.extend_from_slice(raw);
+                self.depth = 0;
+                self.rule = None;
+                false
+            }
+            UrlAction::Replace(bytes) => {
+                self.cur = bytes;
+                self.cur != raw
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn output(&self) -> &[u8] {
+        &self.cur
+    }
+
+    /// Count the rewrite rule that fired for the last URL, once its output is
+    /// written.
+    #[inline]
+    pub(crate) fn commit(&self) {
+        if let (Some(rules), Some(rule)) = (&self.options.rewrite, self.rule) {
+            rules.count_hit(rule);
+        }
+    }
+
+    /// How many decoding passes changed the last URL.
+    #[inline]
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+/// Whether `url` has the `file` scheme.
+#[inline]
+pub(crate) fn is_file_url(url: &[u8]) -> bool {
+    url.len() >= 7 && url[..7].eq_ignore_ascii_case(b"file://")
+}
+
+/// Decode `url` once. With `nested`, query parameter values holding a URL keep
+/// the bytes of [`NESTED_KEEP`] encoded, so that the outer URL stays valid.
+fn decode_pass(url: &[u8], rules: Rules, nested: bool, out: &mut Vec<u8>) -> bool {
+    let query_start = match memchr(b'?', url) {
+        Some(idx) if nested => idx + 1,
+        _ => return decode_component(url, rules, out),
+    };
+    let query_end = memchr(b'#', &url[query_start..]).map_or(url.len(), |idx| query_start + idx);
+
+    let mut changed = decode_component(&url[..query_start], rules, out);
+    for (i, param) in url[query_start..query_end]
+        .split(|&b| b == b'&')
+        .enumerate()
+    {
+        if i > 0 {
+            out.push(b'&');
+        }
+        let (key, value) = split_param(param);
+        changed |= decode_component(key, rules, out);
+        let value_rules = if is_url_value(value) {
+            rules.keeping(NESTED_KEEP)
+        } else {
+            rules
+        };
+        changed |= decode_component(value, value_rules, out);
+    }
+    changed |= decode_component(&url[query_end..], rules, out);
+    changed
+}
+
+/// Remove the query parameters whose name matches one of `patterns` (see
+/// [`DecodeOptions::strip_params`]), writing the URL to `out`. Returns whether
+/// any parameter was removed.
+fn strip_params(url: &[u8], patterns: &[String], out: &mut Vec<u8>) -> bool {
+    let Some(query_start) = memchr(b'?', url).map(|idx| idx + 1) else {
+        return false;
+    };
+    let query_end = memchr(b'#', &url[query_start..]).map_or(url.len(), |idx| query_start + idx);
+
+    out.extend_from_slice(&url[..query_start]);
+    let mut stripped = false;
+    let mut kept = 0;
+    for param in url[query_start..query_end].split(|&b| b == b'&') {
+        if param.is_empty() {
+            continue;
+        }
+        let name = memchr(b'=', param).map_or(param, |idx| &param[..idx]);
+        if patterns.iter().any(|p| param_matches(p.as_bytes(), name)) {
+            stripped = true;
+            continue;
+        }
+        if kept > 0 {
+            out.push(b'&');
+        }
+        out.extend_from_slice(param);
+        kept += 1;
+    }
+    if kept == 0 {
+        // drop the `?` of an emptied query
+        out.pop();
+    }
+    out.extend_from_slice(&url[query_end..]);
+    stripped
+}
+
+/// Match a parameter name against a pattern, either an exact name or a
+/// prefix ending with `*`. Case insensitive.
+#[inline]
+fn param_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.strip_suffix(b"*") {
+        Some(prefix) => {
+            name.len() >= prefix.len() && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+        }
+        None => name.eq_ignore_ascii_case(pattern),
+    }
+}
+
+/// Split a query parameter after its `=`.
+#[inline]
+fn split_param(param: &[u8]) -> (&[u8], &[u8]) {
+    match memchr(b'=', param) {
+        Some(idx) => param.split_at(idx + 1),
+        None => (param, &[]),
+    }
+}
+
+/// Whether a query parameter value is a URL, either raw or encoded.
+fn is_url_value(value: &[u8]) -> bool {
+    const PREFIXES: [&[u8]; 4] = [b"http://", b"https://", b"http%3a%2f%2f", b"https%3a%2f%2f"];
+    PREFIXES.iter().any(|prefix| {
+        value.len() >= prefix.len() && value[..prefix.len()].eq_ignore_ascii_case(prefix)
+    })
+}
+
+/// Decode escapes of `src` into `out` following `rules`. Returns whether
+/// anything was decoded.
+pub(crate) fn decode_component(src: &[u8], rules: Rules, out: &mut Vec<u8>) -> bool {
+    let len = src.len();
+    let mut changed = false;
+    let mut i = 0;
+    let mut literal_start = 0;
+
+    while let Some(offset) = memchr(b'%', &src[i..]) {
+        i += offset;
+        let decoded = if i + 2 < len {
+            decode_hex_pair(src[i + 1], src[i + 2])
+        } else {
+            None
+        };
+        let Some(decoded) = decoded else {
+            if rules.percent_u {
+                if let Some((ch, esc_len)) = decode_percent_u(&src[i..]) {
+                    let kept =
+                        ch.is_ascii() && (rules.non_ascii_only || rules.keep.contains(ch as u8));
+                    if !kept {
+                        out.extend_from_slice(&src[literal_start..i]);
+                        out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+                        changed = true;
+                        i += esc_len;
+                        literal_start = i;
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+            continue;
+        };
+
+        let char_len = if rules.non_ascii_only {
+            decode_utf8_char(src, i, decoded, out, literal_start)
+        } else if !rules.keep.contains(decoded) {
+            out.extend_from_slice(&src[literal_start..i]);
+            out.push(decoded);
+            Some(1)
+        } else {
+            None
+        };
+        match char_len {
+            Some(char_len) => {
+                changed = true;
+                i += char_len * 3;
+                literal_start = i;
+            }
+            // kept encoded
+            None => {
+                if rules.uppercase
+                    && (src[i + 1].is_ascii_lowercase() || src[i + 2].is_ascii_lowercase())
+                {
+                    out.extend_from_slice(&src[literal_start..i]);
+                    push_escape(out, decoded);
+                    changed = true;
+                    literal_start = i + 3;
+                }
+                i += 3;
+            }
+        }
+    }
+    out.extend_from_slice(&src[literal_start..]);
+    changed
+}
+
+/// Decode the `%uXXXX` escape at the start of `src`, or a surrogate pair of
+/// them. Returns the character and the length of the escapes.
+fn decode_percent_u(src: &[u8]) -> Option<(char, usize)> {
+    let unit = percent_u_unit(src)?;
+    if (0xD800..=0xDBFF).contains(&unit) {
+        let low = percent_u_unit(&src[6..]).filter(|low| (0xDC00..=0xDFFF).contains(low))?;
+        let code = 0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00);
+        return char::from_u32(code).map(|ch| (ch, 12));
+    }
+    // a lone low surrogate is not a char
+    char::from_u32(unit.into()).map(|ch| (ch, 6))
+}
+
+/// The UTF-16 code unit of a `%uXXXX` escape at the start of `src`.
+#[inline]
+fn percent_u_unit(src: &[u8]) -> Option<u16> {
+    if src.len() < 6 || src[0] != b'%' || !matches!(src[1], b'u' | b'U') {
+        return None;
+    }
+    let hi = decode_hex_pair(src[2], src[3])?;
+    let lo = decode_hex_pair(src[4], src[5])?;
+    Some(u16::from_be_bytes([hi, lo]))
+}
+
+/// Decode the escapes at `src[i..]`, whose first byte is `lead`, if they form a
+/// complete non-ASCII UTF-8 character. The pending literal and the character
+/// are written to `out`. Returns the length of the character.
+#[inline]
+fn decode_utf8_char(
+    src: &[u8],
+    i: usize,
+    lead: u8,
+    out: &mut Vec<u8>,
+    literal_start: usize,
+) -> Option<usize> {
+    let char_len = match lead {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+    let mut buf = [lead, 0, 0, 0];
+    for (k, byte) in buf.iter_mut().enumerate().take(char_len).skip(1) {
+        let at = i + k * 3;
+        if at + 2 >= src.len() || src[at] != b'%' {
+            return None;
+        }
+        *byte = decode_hex_pair(src[at + 1], src[at + 2])?;
+    }
+    // rejects overlong forms, surrogates and bad continuation bytes
+    std::str::from_utf8(&buf[..char_len]).ok()?;
+
+    out.extend_from_slice(&src[literal_start..i]);
+    out.extend_from_slice(&buf[..char_len]);
+    Some(char_len)
+}
+
+/// Extract the URLs nested in query parameter values of `url`, such as the
+/// target of a redirect link, fully decoded for display.
+///
+/// ```
+/// assert_eq!(
+///     urldecoder::nested_urls("https://a.com/r?url=https%3A%2F%2Fb.com%2F%25E4%25B8%25AD&x=1"),
+///     ["https://b.com/中"]
+/// );
+/// ```
+pub fn nested_urls(url: &str) -> Vec<String> {
+    let url = url.as_bytes();
+    let Some(query_start) = memchr(b'?', url).map(|idx| idx + 1) else {
+        return Vec::new();
+    };
+    let query_end = memchr(b'#', &url[query_start..]).map_or(url.len(), |idx| query_start + idx);
+
+    let mut value_buf = Vec::new();
+    url[query_start..query_end]
+        .split(|&b| b == b'&')
+        .map(|param| split_param(param).1)
+        .filter(|value| is_url_value(value))
+        .map(|value| {
+            // the value is decoded once by the outer URL, then the URL itself
+            value_buf.clear();
+            decode_component(value, Rules::ALL, &mut value_buf);
+            let mut inner = Vec::with_capacity(value_buf.len());
+            decode_component(&value_buf, Rules::ALL, &mut inner);
+            String::from_utf8_lossy(&inner).into_owned()
+        })
+        .collect()
+}